@@ -0,0 +1,724 @@
+// This module ties a principal to the Bitcoin address the smart contract controls on its
+// behalf. It derives the threshold public key for the requested address type, builds the
+// matching address, and dispatches transaction building and signing to the module that
+// implements that address type. It also keeps track of the fresh receive addresses handed
+// out for an account.
+
+use std::collections::BTreeMap;
+
+use bitcoin::{
+    hashes::Hash, key::Secp256k1, Address, CompressedPublicKey, OutPoint, ScriptBuf, Transaction,
+    TxOut, XOnlyPublicKey,
+};
+use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::bitcoin_canister::{
+    bitcoin_get_balance, bitcoin_get_utxos, GetBalanceRequest, GetUtxosRequest, GetUtxosResponse,
+    MillisatoshiPerByte, Outpoint, Utxo,
+};
+
+use crate::{
+    btc::{outpoint, PrimaryOutput},
+    coin_control::is_spendable,
+    coin_selection::{CoinSelection, CoinSelectionStrategy, Required, SelectAll},
+    ecdsa::{get_ecdsa_public_key, sign_with_ecdsa},
+    p2pkh, p2tr, p2wpkh,
+    schnorr::{get_schnorr_public_key, sign_with_schnorr},
+    state::{mutate_state, read_state},
+    vsize, BitcoinContext,
+};
+
+/// Identifies one of a principal's accounts.
+///
+/// Account 0 is the default account every principal has; further accounts are created
+/// explicitly and numbered from 1.
+pub type AccountId = u32;
+
+pub const DEFAULT_ACCOUNT: AccountId = 0;
+
+/// Upper bound on the number of accounts per principal, including the default account.
+pub const MAX_ACCOUNTS: usize = 100;
+
+/// Upper bound on the length of an account label, in bytes.
+pub const MAX_LABEL_LENGTH: usize = 64;
+
+/// The kind of address an account is held at.
+///
+/// P2TR accounts are signed with threshold Schnorr, P2WPKH and legacy P2PKH accounts with
+/// threshold ECDSA. When no address type is given, endpoints fall back to P2TR.
+///
+/// Inheritance accounts are P2TR accounts whose script tree lets the principal's heir spend
+/// outputs that have not moved for the configured number of blocks. They only exist once the
+/// principal has set up an inheritance plan.
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AddressType {
+    #[default]
+    #[serde(rename = "p2tr")]
+    P2tr,
+    #[serde(rename = "p2wpkh")]
+    P2wpkh,
+    #[serde(rename = "p2pkh")]
+    P2pkh,
+    #[serde(rename = "inheritance")]
+    Inheritance,
+}
+
+/// Upper bound on the number of consecutive receive addresses of an account that have been
+/// issued but never received funds. Wallets recovering the account stop scanning after this
+/// many unused addresses, so funds sent beyond it could go unnoticed.
+pub const GAP_LIMIT: u32 = 20;
+
+/// Book-keeping of the receive addresses issued for one account and address type.
+#[derive(CandidType, Deserialize, Clone, Copy, Default)]
+pub struct ReceiveAddresses {
+    /// The highest issued index; indices `1..=issued` have been handed out.
+    pub issued: u32,
+    /// The highest index that has been seen holding funds, or 0 if none has.
+    pub last_used: u32,
+}
+
+/// One of the addresses an account receives funds at.
+pub struct ReceiveAddress {
+    pub index: u32,
+    pub derivation_path: Vec<Vec<u8>>,
+    /// SEC1-encoded (compressed) public key at `derivation_path`.
+    pub public_key: Vec<u8>,
+    pub address: Address,
+}
+
+/// A single-key account derived for a principal.
+///
+/// Besides its main address, which also receives the change of every transaction, an account
+/// can hand out fresh receive addresses so that payments cannot be linked on-chain. Receive
+/// addresses are only known after [`Account::load_receive_addresses`]; balances and UTXOs
+/// cover all known addresses.
+pub struct Account {
+    pub principal: Principal,
+    pub account_id: AccountId,
+    pub address_type: AddressType,
+    pub derivation_path: Vec<Vec<u8>>,
+    /// SEC1-encoded (compressed) public key at `derivation_path`.
+    pub public_key: Vec<u8>,
+    pub address: Address,
+    /// Merkle root of the script tree the key is tweaked with. Empty for accounts without one.
+    pub merkle_root_hash: Vec<u8>,
+    /// The receive addresses loaded so far, in order of their index.
+    pub receive_addresses: Vec<ReceiveAddress>,
+    /// The strategy for choosing the UTXOs a transaction spends.
+    pub coin_selection: CoinSelectionStrategy,
+    /// The script of the address holding each UTXO returned by [`Account::get_utxos`].
+    utxo_owners: BTreeMap<OutPoint, ScriptBuf>,
+    /// The height of the chain tip as of the last call to [`Account::get_utxos`].
+    tip_height: u32,
+}
+
+impl Account {
+    /// Derives account `account_id` of `principal` for the given address type.
+    ///
+    /// All address types use the same derivation path, see [`derivation_path`]. P2TR
+    /// addresses are unrelated to the others since Schnorr and ECDSA keys are derived from
+    /// different master keys, while the P2WPKH and P2PKH addresses share the same ECDSA key.
+    /// Inheritance accounts use the P2TR key as internal key.
+    ///
+    /// Returns an error if the principal has no account with the given id, and for an
+    /// inheritance account of a principal without an inheritance plan. Only the default
+    /// account can be an inheritance account.
+    pub async fn derive(
+        ctx: &BitcoinContext,
+        principal: Principal,
+        account_id: AccountId,
+        address_type: AddressType,
+    ) -> Result<Account, String> {
+        ensure_account_exists(principal, account_id)?;
+        let derivation_path = derivation_path(principal, account_id);
+        let mut merkle_root_hash = vec![];
+
+        let (public_key, address) = match address_type {
+            AddressType::Inheritance => {
+                if account_id != DEFAULT_ACCOUNT {
+                    return Err("Only the default account has an inheritance address".to_string());
+                }
+                let plan = read_state(|state| state.inheritance_plans.get(&principal).cloned())
+                    .ok_or("No inheritance plan set up")?;
+                let public_key = get_schnorr_public_key(ctx, derivation_path.clone()).await;
+                let compiled = plan.policy_account(principal).compile(ctx).await?;
+                merkle_root_hash = compiled.merkle_root_hash();
+                (public_key, compiled.address)
+            }
+            _ => derive_single_key_address(ctx, address_type, derivation_path.clone()).await,
+        };
+
+        Ok(Account {
+            principal,
+            account_id,
+            address_type,
+            derivation_path,
+            public_key,
+            address,
+            merkle_root_hash,
+            receive_addresses: vec![],
+            coin_selection: read_state(|state| {
+                state
+                    .coin_selection
+                    .get(&(principal, account_id))
+                    .copied()
+                    .unwrap_or_default()
+            }),
+            utxo_owners: BTreeMap::new(),
+            tip_height: 0,
+        })
+    }
+
+    /// Derives the receive addresses issued for this account so far.
+    pub async fn load_receive_addresses(&mut self, ctx: &BitcoinContext) {
+        let issued = read_state(|state| {
+            state
+                .receive_addresses
+                .get(&self.receive_addresses_key())
+                .map_or(0, |receive_addresses| receive_addresses.issued)
+        });
+        for index in self.receive_addresses.len() as u32 + 1..=issued {
+            let receive_address = self.derive_receive_address(ctx, index).await;
+            self.receive_addresses.push(receive_address);
+        }
+    }
+
+    /// Issues the next receive address of this account.
+    ///
+    /// Fails once [`GAP_LIMIT`] issued addresses in a row have never received funds. Before
+    /// failing, the unused addresses are checked for funds that arrived since they were last
+    /// looked at. Inheritance accounts only have their main address.
+    pub async fn issue_receive_address(
+        &mut self,
+        ctx: &BitcoinContext,
+    ) -> Result<&Address, String> {
+        if self.address_type == AddressType::Inheritance {
+            return Err("Inheritance accounts do not support receive addresses".to_string());
+        }
+        self.load_receive_addresses(ctx).await;
+
+        let key = self.receive_addresses_key();
+        let receive_addresses =
+            read_state(|state| state.receive_addresses.get(&key).copied()).unwrap_or_default();
+        if receive_addresses.issued - receive_addresses.last_used >= GAP_LIMIT {
+            for receive_address in self.receive_addresses.iter() {
+                if receive_address.index > receive_addresses.last_used
+                    && !get_utxos(ctx, &receive_address.address)
+                        .await?
+                        .utxos
+                        .is_empty()
+                {
+                    record_usage(key, receive_address.index);
+                }
+            }
+        }
+
+        // Reserve the index before any further await, so that concurrent calls never hand out
+        // the same address twice.
+        let index = mutate_state(|state| {
+            let receive_addresses = state.receive_addresses.entry(key).or_default();
+            if receive_addresses.issued - receive_addresses.last_used >= GAP_LIMIT {
+                return Err(format!(
+                    "Gap limit reached: the last {} receive addresses have not received funds yet",
+                    GAP_LIMIT
+                ));
+            }
+            receive_addresses.issued += 1;
+            Ok(receive_addresses.issued)
+        })?;
+
+        self.load_receive_addresses(ctx).await;
+        Ok(&self.receive_addresses[index as usize - 1].address)
+    }
+
+    /// Returns the UTXOs held by the main address and all loaded receive addresses.
+    ///
+    /// Receive addresses found holding funds are recorded as used, which moves the gap limit
+    /// window forward.
+    pub async fn get_utxos(&mut self, ctx: &BitcoinContext) -> Result<Vec<Utxo>, String> {
+        let key = self.receive_addresses_key();
+        let mut own_utxos = vec![];
+        for (index, address) in self.addresses() {
+            let GetUtxosResponse {
+                utxos, tip_height, ..
+            } = get_utxos(ctx, &address).await?;
+            self.tip_height = self.tip_height.max(tip_height);
+            if !utxos.is_empty() {
+                record_usage(key, index);
+            }
+            for utxo in utxos.iter() {
+                self.utxo_owners
+                    .insert(outpoint(utxo), address.script_pubkey());
+            }
+            own_utxos.extend(utxos);
+        }
+        Ok(own_utxos)
+    }
+
+    /// Returns the outputs of `transaction`, which need not be confirmed, that pay the main
+    /// address or a loaded receive address, as UTXOs of this account at height 0.
+    ///
+    /// They are attributed to the address they pay, like the UTXOs returned by
+    /// [`Account::get_utxos`].
+    pub fn unconfirmed_utxos(&mut self, transaction: &Transaction) -> Vec<Utxo> {
+        let txid = transaction.compute_txid();
+        let mut utxos = vec![];
+        for (vout, output) in transaction.output.iter().enumerate() {
+            if self
+                .addresses()
+                .iter()
+                .any(|(_, address)| address.script_pubkey() == output.script_pubkey)
+            {
+                let utxo = Utxo {
+                    outpoint: Outpoint {
+                        txid: txid.to_byte_array().to_vec(),
+                        vout: vout as u32,
+                    },
+                    value: output.value.to_sat(),
+                    height: 0,
+                };
+                self.utxo_owners
+                    .insert(outpoint(&utxo), output.script_pubkey.clone());
+                utxos.push(utxo);
+            }
+        }
+        utxos
+    }
+
+    /// Returns the UTXOs like [`Account::get_utxos`], except those protected from ordinary
+    /// coin selection, see [`crate::protection`], and those frozen by the principal, see
+    /// [`crate::coin_control`]. Payments and fees are funded from these.
+    pub async fn get_spendable_utxos(&mut self, ctx: &BitcoinContext) -> Result<Vec<Utxo>, String> {
+        let principal = self.principal;
+        Ok(self
+            .get_utxos(ctx)
+            .await?
+            .into_iter()
+            .filter(|utxo| is_spendable(principal, &outpoint(utxo)))
+            .collect())
+    }
+
+    /// The height of the chain tip as seen by the last call to [`Account::get_utxos`], or 0
+    /// before the first call.
+    pub fn tip_height(&self) -> u32 {
+        self.tip_height
+    }
+
+    /// Returns the balance of the main address and all loaded receive addresses.
+    pub async fn get_balance(&self, ctx: &BitcoinContext) -> Result<u64, String> {
+        let key = self.receive_addresses_key();
+        let mut total = 0;
+        for (index, address) in self.addresses() {
+            let balance = bitcoin_get_balance(&GetBalanceRequest {
+                address: address.to_string(),
+                network: ctx.network,
+                min_confirmations: None,
+            })
+            .await
+            .map_err(|e| format!("Failed to get balance: {:?}", e))?;
+            if balance > 0 {
+                record_usage(key, index);
+            }
+            total += balance;
+        }
+        Ok(total)
+    }
+
+    /// Builds an unsigned transaction paying `primary_outputs` from `own_utxos`, with the fee
+    /// sized for this account's address type. The UTXOs to spend are chosen with the
+    /// account's coin selection strategy.
+    ///
+    /// Change goes to the account's main address. UTXOs returned by [`Account::get_utxos`]
+    /// are attributed to the address that holds them.
+    pub async fn build_transaction(
+        &self,
+        ctx: &BitcoinContext,
+        own_utxos: &[Utxo],
+        primary_outputs: &[PrimaryOutput],
+        fee_per_byte: MillisatoshiPerByte,
+    ) -> Result<(Transaction, Vec<TxOut>), String> {
+        self.build_transaction_selecting(
+            ctx,
+            own_utxos,
+            &self.coin_selection,
+            primary_outputs,
+            fee_per_byte,
+        )
+        .await
+    }
+
+    /// Builds a transaction like [`Self::build_transaction`] that spends exactly `inputs`,
+    /// in the given order, instead of letting coin selection choose.
+    ///
+    /// Inheritance accounts do not support this, since they always spend all outputs.
+    pub async fn build_transaction_from(
+        &self,
+        ctx: &BitcoinContext,
+        inputs: &[Utxo],
+        primary_outputs: &[PrimaryOutput],
+        fee_per_byte: MillisatoshiPerByte,
+    ) -> Result<(Transaction, Vec<TxOut>), String> {
+        if self.address_type == AddressType::Inheritance {
+            return Err("Inheritance accounts always spend all UTXOs".to_string());
+        }
+        self.build_transaction_selecting(ctx, inputs, &SelectAll, primary_outputs, fee_per_byte)
+            .await
+    }
+
+    /// Builds an unsigned transaction that spends all of `own_utxos` and sends their total
+    /// value minus the fee to `destination`, without a change output.
+    pub async fn build_sweep_transaction(
+        &self,
+        ctx: &BitcoinContext,
+        own_utxos: &[Utxo],
+        destination: &Address,
+        fee_per_byte: MillisatoshiPerByte,
+    ) -> Result<(Transaction, Vec<TxOut>), String> {
+        let (transaction, mut prevouts) = match self.address_type {
+            AddressType::P2tr | AddressType::Inheritance => {
+                p2tr::build_sweep_transaction(
+                    ctx,
+                    &self.address,
+                    own_utxos,
+                    &[],
+                    destination,
+                    fee_per_byte,
+                    None,
+                )
+                .await
+            }
+            AddressType::P2wpkh => {
+                p2wpkh::build_sweep_transaction(
+                    ctx,
+                    &self.compressed_public_key(),
+                    &self.address,
+                    own_utxos,
+                    destination,
+                    fee_per_byte,
+                )
+                .await
+            }
+            AddressType::P2pkh => {
+                p2pkh::build_sweep_transaction(
+                    ctx,
+                    &self.compressed_public_key(),
+                    &self.address,
+                    own_utxos,
+                    destination,
+                    fee_per_byte,
+                )
+                .await
+            }
+        }?;
+
+        self.set_prevout_owners(&transaction, &mut prevouts);
+        Ok((transaction, prevouts))
+    }
+
+    /// Builds a transaction like [`Self::build_transaction`] that spends all of
+    /// `required_utxos` by its first inputs, topped up from `own_utxos` to cover the outputs
+    /// and the fee.
+    ///
+    /// Inheritance accounts do not support this, since they always spend all outputs.
+    pub async fn build_transaction_spending(
+        &self,
+        ctx: &BitcoinContext,
+        required_utxos: &[Utxo],
+        own_utxos: &[Utxo],
+        primary_outputs: &[PrimaryOutput],
+        fee_per_byte: MillisatoshiPerByte,
+    ) -> Result<(Transaction, Vec<TxOut>), String> {
+        if self.address_type == AddressType::Inheritance {
+            return Err("Inheritance accounts always spend all UTXOs".to_string());
+        }
+        self.build_transaction_selecting(
+            ctx,
+            &[required_utxos, own_utxos].concat(),
+            &Required {
+                required: required_utxos,
+                then: &self.coin_selection,
+            },
+            primary_outputs,
+            fee_per_byte,
+        )
+        .await
+    }
+
+    async fn build_transaction_selecting(
+        &self,
+        ctx: &BitcoinContext,
+        own_utxos: &[Utxo],
+        coin_selection: &dyn CoinSelection,
+        primary_outputs: &[PrimaryOutput],
+        fee_per_byte: MillisatoshiPerByte,
+    ) -> Result<(Transaction, Vec<TxOut>), String> {
+        // All addresses of an account have the same type, so the fee estimate does not depend
+        // on which of them the inputs belong to.
+        let (transaction, mut prevouts) = match self.address_type {
+            AddressType::P2tr => {
+                p2tr::build_transaction(
+                    ctx,
+                    &self.address,
+                    own_utxos,
+                    coin_selection,
+                    primary_outputs,
+                    fee_per_byte,
+                )
+                .await
+            }
+            AddressType::Inheritance => {
+                // Spending every output re-creates the remainder as a single fresh change
+                // output, which restarts the heir's relative timelock for all funds.
+                p2tr::build_transaction(
+                    ctx,
+                    &self.address,
+                    own_utxos,
+                    &SelectAll,
+                    primary_outputs,
+                    fee_per_byte,
+                )
+                .await
+            }
+            AddressType::P2wpkh => {
+                p2wpkh::build_transaction(
+                    ctx,
+                    &self.compressed_public_key(),
+                    &self.address,
+                    own_utxos,
+                    coin_selection,
+                    primary_outputs,
+                    fee_per_byte,
+                )
+                .await
+            }
+            AddressType::P2pkh => {
+                p2pkh::build_transaction(
+                    ctx,
+                    &self.compressed_public_key(),
+                    &self.address,
+                    own_utxos,
+                    coin_selection,
+                    primary_outputs,
+                    fee_per_byte,
+                )
+                .await
+            }
+        }?;
+
+        self.set_prevout_owners(&transaction, &mut prevouts);
+        Ok((transaction, prevouts))
+    }
+
+    /// Signs every input of `transaction` with the threshold key of the address it spends.
+    pub async fn sign_transaction(
+        &self,
+        ctx: &BitcoinContext,
+        transaction: Transaction,
+        prevouts: &[TxOut],
+    ) -> Transaction {
+        let keys: Vec<_> = prevouts
+            .iter()
+            .map(|prevout| self.key_of(&prevout.script_pubkey))
+            .collect();
+
+        match self.address_type {
+            AddressType::P2tr | AddressType::Inheritance => {
+                let derivation_paths: Vec<_> = keys
+                    .into_iter()
+                    .map(|(_, derivation_path)| derivation_path.clone())
+                    .collect();
+                p2tr::sign_transaction_key_spend(
+                    ctx,
+                    transaction,
+                    prevouts,
+                    &derivation_paths,
+                    self.merkle_root_hash.clone(),
+                    sign_with_schnorr,
+                )
+                .await
+            }
+            AddressType::P2wpkh | AddressType::P2pkh => {
+                let keys: Vec<_> = keys
+                    .into_iter()
+                    .map(|(public_key, derivation_path)| {
+                        (
+                            CompressedPublicKey::from_slice(public_key).unwrap(),
+                            derivation_path.clone(),
+                        )
+                    })
+                    .collect();
+                if self.address_type == AddressType::P2wpkh {
+                    p2wpkh::sign_transaction(ctx, transaction, prevouts, &keys, sign_with_ecdsa)
+                        .await
+                } else {
+                    p2pkh::sign_transaction(ctx, transaction, prevouts, &keys, sign_with_ecdsa)
+                        .await
+                }
+            }
+        }
+    }
+
+    /// The virtual size `transaction` is predicted to have once signed, see [`crate::vsize`].
+    pub fn predicted_vsize(&self, transaction: &Transaction) -> u64 {
+        let input = match self.address_type {
+            AddressType::P2tr | AddressType::Inheritance => vsize::P2TR_KEY_PATH,
+            AddressType::P2wpkh => vsize::P2WPKH,
+            AddressType::P2pkh => vsize::P2PKH,
+        };
+        vsize::predict_vsize(input, transaction.input.len(), &transaction.output)
+    }
+
+    fn compressed_public_key(&self) -> CompressedPublicKey {
+        CompressedPublicKey::from_slice(&self.public_key).unwrap()
+    }
+
+    /// The main address and the loaded receive addresses, with their index.
+    fn addresses(&self) -> Vec<(u32, Address)> {
+        std::iter::once((0, self.address.clone()))
+            .chain(
+                self.receive_addresses.iter().map(|receive_address| {
+                    (receive_address.index, receive_address.address.clone())
+                }),
+            )
+            .collect()
+    }
+
+    /// The public key and derivation path of the address with the given script.
+    fn key_of(&self, script_pubkey: &ScriptBuf) -> (&Vec<u8>, &Vec<Vec<u8>>) {
+        self.receive_addresses
+            .iter()
+            .find(|receive_address| receive_address.address.script_pubkey() == *script_pubkey)
+            .map_or(
+                (&self.public_key, &self.derivation_path),
+                |receive_address| {
+                    (
+                        &receive_address.public_key,
+                        &receive_address.derivation_path,
+                    )
+                },
+            )
+    }
+
+    /// Replaces the placeholder scripts of the builders, which assume that every input spends
+    /// the main address, with the scripts of the addresses that own the inputs.
+    fn set_prevout_owners(&self, transaction: &Transaction, prevouts: &mut [TxOut]) {
+        for (input, prevout) in transaction.input.iter().zip(prevouts.iter_mut()) {
+            if let Some(script_pubkey) = self.utxo_owners.get(&input.previous_output) {
+                prevout.script_pubkey = script_pubkey.clone();
+            }
+        }
+    }
+
+    fn receive_addresses_key(&self) -> (Principal, AccountId, AddressType) {
+        (self.principal, self.account_id, self.address_type)
+    }
+
+    async fn derive_receive_address(&self, ctx: &BitcoinContext, index: u32) -> ReceiveAddress {
+        let mut derivation_path = self.derivation_path.clone();
+        derivation_path.push(b"receive".to_vec());
+        derivation_path.push(index.to_be_bytes().to_vec());
+        let (public_key, address) =
+            derive_single_key_address(ctx, self.address_type, derivation_path.clone()).await;
+        ReceiveAddress {
+            index,
+            derivation_path,
+            public_key,
+            address,
+        }
+    }
+}
+
+// Derives the public key at `derivation_path` and the address of the given type it controls.
+// Inheritance addresses are not single-key addresses and are derived in `Account::derive`.
+async fn derive_single_key_address(
+    ctx: &BitcoinContext,
+    address_type: AddressType,
+    derivation_path: Vec<Vec<u8>>,
+) -> (Vec<u8>, Address) {
+    match address_type {
+        AddressType::P2tr => {
+            // Derive the public key used as the internal key (untweaked key path base).
+            let public_key = get_schnorr_public_key(ctx, derivation_path).await;
+            let internal_key =
+                XOnlyPublicKey::from(bitcoin::PublicKey::from_slice(&public_key).unwrap());
+
+            // Create a Taproot address using the internal key only.
+            // We pass `None` as the Merkle root, which per BIP-341 means the address commits
+            // to an unspendable script path, enabling only key path spending.
+            let secp256k1_engine = Secp256k1::new();
+            let address = Address::p2tr(&secp256k1_engine, internal_key, None, ctx.bitcoin_network);
+            (public_key, address)
+        }
+        AddressType::P2wpkh => {
+            let public_key = get_ecdsa_public_key(ctx, derivation_path).await;
+            let address = Address::p2wpkh(
+                &CompressedPublicKey::from_slice(&public_key).unwrap(),
+                ctx.bitcoin_network,
+            );
+            (public_key, address)
+        }
+        AddressType::P2pkh => {
+            let public_key = get_ecdsa_public_key(ctx, derivation_path).await;
+            let address = Address::p2pkh(
+                CompressedPublicKey::from_slice(&public_key).unwrap(),
+                ctx.bitcoin_network,
+            );
+            (public_key, address)
+        }
+        AddressType::Inheritance => unreachable!("inheritance addresses have a script tree"),
+    }
+}
+
+// Fetches the UTXOs of `address`.
+//
+// Note that pagination may have to be used to get all UTXOs for the given address.
+// For the sake of simplicity, it is assumed here that the `utxo` field in the response
+// contains all UTXOs.
+async fn get_utxos(ctx: &BitcoinContext, address: &Address) -> Result<GetUtxosResponse, String> {
+    bitcoin_get_utxos(&GetUtxosRequest {
+        address: address.to_string(),
+        network: ctx.network,
+        filter: None,
+    })
+    .await
+    .map_err(|e| format!("Failed to get UTXOs: {:?}", e))
+}
+
+// Records that receive address `index` of the account identified by `key` holds funds.
+fn record_usage(key: (Principal, AccountId, AddressType), index: u32) {
+    if index == 0 {
+        return;
+    }
+    mutate_state(|state| {
+        if let Some(receive_addresses) = state.receive_addresses.get_mut(&key) {
+            receive_addresses.last_used = receive_addresses.last_used.max(index);
+        }
+    });
+}
+
+/// The derivation path of account `account_id` of `principal`.
+///
+/// The default account keeps the single-element path used before numbered accounts existed,
+/// so its addresses never change. Other accounts append their big-endian account id.
+pub fn derivation_path(principal: Principal, account_id: AccountId) -> Vec<Vec<u8>> {
+    let mut derivation_path = vec![principal.as_slice().to_vec()];
+    if account_id != DEFAULT_ACCOUNT {
+        derivation_path.push(account_id.to_be_bytes().to_vec());
+    }
+    derivation_path
+}
+
+/// Returns an error unless `principal` has an account with the given id.
+pub fn ensure_account_exists(principal: Principal, account_id: AccountId) -> Result<(), String> {
+    if account_id == DEFAULT_ACCOUNT
+        || read_state(|state| {
+            state
+                .accounts
+                .get(&principal)
+                .is_some_and(|accounts| accounts.contains_key(&account_id))
+        })
+    {
+        Ok(())
+    } else {
+        Err(format!("No account with id {}", account_id))
+    }
+}
@@ -1,13 +1,25 @@
 // This module provides common utilities for Bitcoin transaction construction and management.
-// It includes UTXO selection algorithms, transaction building, fee estimation, and
-// BIP-32 derivation path handling used across all Bitcoin address types.
+// It includes transaction building, fee estimation, and BIP-32 derivation path handling used
+// across all Bitcoin address types. UTXO selection lives in `coin_selection`.
 
 use bitcoin::{
-    self, absolute::LockTime, blockdata::witness::Witness, hashes::Hash, transaction::Version,
+    self,
+    absolute::LockTime,
+    blockdata::witness::Witness,
+    hashes::Hash,
+    transaction::{InputWeightPrediction, Version},
     Address, Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid,
 };
+use candid::{CandidType, Deserialize};
 use ic_cdk::bitcoin_canister::{
-    bitcoin_get_current_fee_percentiles, GetCurrentFeePercentilesRequest, Network, Utxo,
+    bitcoin_get_current_fee_percentiles, GetCurrentFeePercentilesRequest, MillisatoshiPerByte,
+    Network, Utxo,
+};
+
+use crate::{
+    coin_selection::{Selection, SelectionParams},
+    state::read_state,
+    vsize::{fee, predict_vsize},
 };
 
 /// Runtime configuration shared across all Bitcoin-related operations.
@@ -28,119 +40,103 @@ pub struct BitcoinContext {
     pub key_name: &'static str,
 }
 
-/// Selects UTXOs using a greedy algorithm to cover the required amount plus fee.
-///
-/// This function iterates through UTXOs in reverse order (oldest last) and accumulates
-/// them until the total value covers the payment amount plus transaction fee.
-/// This approach helps consolidate older UTXOs and can reduce wallet fragmentation.
+/// Represents a primary output of a Bitcoin transaction.
 ///
-/// Returns an error if the total UTXO value is insufficient to cover the payment and fee.
-pub fn select_utxos_greedy(
-    own_utxos: &[Utxo],
-    amount: u64,
-    fee: u64,
-) -> Result<Vec<&Utxo>, String> {
-    // Greedily select UTXOs in reverse order (oldest last) until we cover amount + fee.
-    let mut utxos_to_spend = vec![];
-    let mut total_spent = 0;
-    for utxo in own_utxos.iter().rev() {
-        total_spent += utxo.value;
-        utxos_to_spend.push(utxo);
-        if total_spent >= amount + fee {
-            break;
+/// This enum allows transaction builders to specify whether they want to send
+/// bitcoin to an address (normal payment) or embed data using OP_RETURN (for
+/// protocols like Runes that store metadata on-chain). A transaction may have
+/// several primary outputs, e.g. a runestone followed by the output receiving
+/// the runes.
+#[derive(Clone)]
+pub enum PrimaryOutput {
+    /// Pay someone (spendable output).
+    Address(Address, u64), // destination address, amount in satoshis
+    /// Embed data (unspendable OP_RETURN output).
+    OpReturn(ScriptBuf), // script already starts with OP_RETURN
+}
+
+impl PrimaryOutput {
+    /// The amount of satoshis the output carries.
+    pub fn amount(&self) -> u64 {
+        match self {
+            PrimaryOutput::Address(_, amount) => *amount,
+            PrimaryOutput::OpReturn(_) => 0,
         }
     }
 
-    // Abort if we can't cover the payment + fee.
-    if total_spent < amount + fee {
-        return Err(format!(
-            "Insufficient balance: {}, trying to transfer {} satoshi with fee {}",
-            total_spent, amount, fee
-        ));
+    /// The transaction output paying this primary output.
+    pub fn tx_out(&self) -> TxOut {
+        match self {
+            PrimaryOutput::Address(addr, amt) => TxOut {
+                script_pubkey: addr.script_pubkey(),
+                value: Amount::from_sat(*amt),
+            },
+            PrimaryOutput::OpReturn(script) => TxOut {
+                script_pubkey: script.clone(),
+                value: Amount::from_sat(0), // OP_RETURN outputs carry no bitcoin value
+            },
+        }
     }
-
-    Ok(utxos_to_spend)
 }
 
-/// Selects a single UTXO that can cover the required amount plus fee.
-///
-/// This function is used when you need to tie a specific operation to a single UTXO,
-/// such as with Bitcoin inscriptions where the asset must be associated with specific
-/// satoshis. It searches for the first UTXO (in reverse order) that has sufficient value.
-///
-/// Returns an error if no single UTXO has enough value to cover the payment and fee.
-pub fn select_one_utxo(own_utxos: &[Utxo], amount: u64, fee: u64) -> Result<Vec<&Utxo>, String> {
-    for utxo in own_utxos.iter().rev() {
-        if utxo.value >= amount + fee {
-            return Ok(vec![&utxo]);
-        }
+/// The outpoint of `utxo` in the representation of the `bitcoin` crate.
+pub fn outpoint(utxo: &Utxo) -> OutPoint {
+    OutPoint {
+        txid: Txid::from_raw_hash(Hash::from_slice(&utxo.outpoint.txid).unwrap()),
+        vout: utxo.outpoint.vout,
     }
-
-    Err(format!(
-        "No sufficiently large utxo found: amount {} satoshi, fee {}",
-        amount, fee
-    ))
 }
 
-/// Represents the primary output type for a Bitcoin transaction.
+/// The smallest value of an output paying to `address`. Smaller outputs cost more to spend
+/// than they are worth, and nodes do not relay them.
 ///
-/// This enum allows transaction builders to specify whether they want to send
-/// bitcoin to an address (normal payment) or embed data using OP_RETURN (for
-/// protocols like Runes that store metadata on-chain).
-pub enum PrimaryOutput {
-    /// Pay someone (spendable output).
-    Address(Address, u64), // destination address, amount in satoshis
-    /// Embed data (unspendable OP_RETURN output).
-    #[allow(dead_code)]
-    OpReturn(ScriptBuf), // script already starts with OP_RETURN
+/// This is the one dust rule of the smart contract: change outputs, swept amounts and the
+/// postage of inscriptions and runes are all held to it, so they cannot contradict each other.
+pub fn dust_limit(address: &Address) -> u64 {
+    address.script_pubkey().minimal_non_dust().to_sat()
 }
 
-/// Constructs a Bitcoin transaction from the given UTXOs and primary output specification.
+/// Constructs a Bitcoin transaction from the given UTXOs and primary output specifications.
 ///
 /// This function handles the common pattern of Bitcoin transaction construction:
 /// 1. Creates inputs from the selected UTXOs
-/// 2. Creates the primary output (payment or OP_RETURN data)
-/// 3. Adds a change output if the remainder exceeds the dust threshold
+/// 2. Creates the primary outputs (payments or OP_RETURN data), in the given order
+/// 3. Adds a change output if the remainder reaches the dust limit
 /// 4. Returns both the unsigned transaction and previous outputs needed for signing
 ///
 /// The change output is sent back to `own_address` to prevent value loss, but only
-/// if the change amount is above the dust threshold to avoid creating uneconomical outputs.
+/// if the change amount reaches the dust limit to avoid creating uneconomical outputs.
 ///
 /// Returns the constructed unsigned transaction and the list of previous outputs (`prevouts`)
 /// used for signing different address types (P2WPKH, P2TR, etc.).
 ///
 /// Assumes that:
 /// - Inputs are unspent and valid (caller's responsibility)
-/// - Dust threshold is 1,000 satoshis (outputs below this are omitted)
-/// - UTXOs are already filtered to be spendable (confirmed, mature, etc.)
+/// - Change below the dust limit of `own_address` is omitted, see [`dust_limit`]
+/// - UTXOs are already filtered to be spendable (mature, etc.); they may be unconfirmed, as
+///   when a child pays for its parent, see [`child_fee`]
 pub fn build_transaction_with_fee(
     utxos_to_spend: Vec<&Utxo>,
     own_address: &Address,
-    primary_output: &PrimaryOutput,
+    primary_outputs: &[PrimaryOutput],
     fee: u64,
 ) -> Result<(Transaction, Vec<TxOut>), String> {
-    // Define a dust threshold below which change outputs are discarded.
-    // This prevents creating outputs that cost more to spend than they're worth.
-    const DUST_THRESHOLD: u64 = 1_000;
-
     // --- Build Inputs ---
     // Convert UTXOs into transaction inputs, preparing them for signing.
     let inputs: Vec<TxIn> = utxos_to_spend
         .iter()
         .map(|utxo| TxIn {
-            previous_output: OutPoint {
-                txid: Txid::from_raw_hash(Hash::from_slice(&utxo.outpoint.txid).unwrap()),
-                vout: utxo.outpoint.vout,
-            },
+            previous_output: outpoint(utxo),
             sequence: Sequence::MAX,      // No relative timelock constraints
             witness: Witness::new(),      // Will be filled in during signing
-            script_sig: ScriptBuf::new(), // Empty for SegWit and Taproot (uses witness)
+            script_sig: ScriptBuf::new(), // Set when signing P2PKH; empty for SegWit and Taproot
         })
         .collect();
 
     // --- Create Previous Outputs ---
     // Each TxOut represents an output from previous transactions being spent.
-    // This data is required for signing P2WPKH and P2TR transactions.
+    // This data is required for signing P2WPKH and P2TR transactions; legacy
+    // P2PKH signatures only commit to the previous output's script.
     let prevouts = utxos_to_spend
         .clone()
         .into_iter()
@@ -151,28 +147,17 @@ pub fn build_transaction_with_fee(
         .collect();
 
     // --- Build Outputs ---
-    // Create the primary output based on the operation type.
-    let mut outputs = Vec::<TxOut>::new();
-
-    match primary_output {
-        PrimaryOutput::Address(addr, amt) => outputs.push(TxOut {
-            script_pubkey: addr.script_pubkey(),
-            value: Amount::from_sat(*amt),
-        }),
-        PrimaryOutput::OpReturn(script) => outputs.push(TxOut {
-            script_pubkey: script.clone(),
-            value: Amount::from_sat(0), // OP_RETURN outputs carry no bitcoin value
-        }),
-    }
+    // Create the primary outputs based on the operation type.
+    let mut outputs: Vec<TxOut> = primary_outputs.iter().map(PrimaryOutput::tx_out).collect();
 
-    // Calculate change and add change output if above dust threshold.
+    // Calculate change and add change output if it reaches the dust limit.
     // This prevents value loss while avoiding uneconomical outputs.
     let total_in: u64 = utxos_to_spend.iter().map(|u| u.value).sum();
     let change = total_in
         .checked_sub(outputs.iter().map(|o| o.value.to_sat()).sum::<u64>() + fee)
         .ok_or("fee exceeds inputs")?;
 
-    if change >= DUST_THRESHOLD {
+    if change >= dust_limit(own_address) {
         outputs.push(TxOut {
             script_pubkey: own_address.script_pubkey(),
             value: Amount::from_sat(change),
@@ -192,11 +177,202 @@ pub fn build_transaction_with_fee(
     ))
 }
 
+/// The fee `transaction` pays when spending `prevouts`.
+pub fn transaction_fee(transaction: &Transaction, prevouts: &[TxOut]) -> u64 {
+    let total_in: u64 = prevouts.iter().map(|prevout| prevout.value.to_sat()).sum();
+    let total_out: u64 = transaction
+        .output
+        .iter()
+        .map(|output| output.value.to_sat())
+        .sum();
+    total_in - total_out
+}
+
+/// The fee a child transaction of `child_vsize` virtual bytes has to pay so that it and its
+/// unconfirmed parent, which pays `parent_fee` for `parent_vsize` virtual bytes, together pay
+/// `fee_per_byte` (child-pays-for-parent). Zero if the parent alone already pays that much.
+pub fn child_fee(
+    parent_fee: u64,
+    parent_vsize: u64,
+    child_vsize: u64,
+    fee_per_byte: MillisatoshiPerByte,
+) -> u64 {
+    ((parent_vsize + child_vsize) * fee_per_byte)
+        .div_ceil(1000)
+        .saturating_sub(parent_fee)
+}
+
+/// Builds a transaction like [`build_transaction_with_fee`] that spends the UTXOs of
+/// `selection`, with the fee for its size predicted from how the inputs are signed, see
+/// [`SelectionParams::input`].
+///
+/// Returns the predicted virtual size along with the transaction and its previous outputs.
+pub fn build_selected_transaction(
+    selection: &Selection,
+    params: &SelectionParams,
+    own_address: &Address,
+    primary_outputs: &[PrimaryOutput],
+) -> Result<(Transaction, Vec<TxOut>, u64), String> {
+    let mut outputs: Vec<TxOut> = primary_outputs.iter().map(PrimaryOutput::tx_out).collect();
+    if selection.change {
+        outputs.push(TxOut {
+            value: Amount::ZERO,
+            script_pubkey: own_address.script_pubkey(),
+        });
+    }
+    // Should the change turn out to be dust, it is left out and the fee is a little higher
+    // than needed.
+    let vsize = predict_vsize(params.input, selection.utxos.len(), &outputs);
+    let fee = selection.fee(params, fee(vsize, params.fee_per_byte));
+    let (transaction, prevouts) =
+        build_transaction_with_fee(selection.utxos.clone(), own_address, primary_outputs, fee)?;
+    Ok((transaction, prevouts, vsize))
+}
+
+/// Builds a transaction that spends all of `utxos`, whose inputs are signed like `input`,
+/// pays `fixed_outputs` and sends the remaining value minus the fee to `destination`, which
+/// comes last, without a change output. Fails if the remaining value would be dust for the
+/// script of `destination`.
+///
+/// Returns the predicted virtual size along with the transaction and its previous outputs.
+pub fn build_sweep_with_predicted_fee(
+    utxos: &[Utxo],
+    own_address: &Address,
+    input: InputWeightPrediction,
+    fixed_outputs: &[PrimaryOutput],
+    destination: &Address,
+    fee_per_byte: MillisatoshiPerByte,
+) -> Result<(Transaction, Vec<TxOut>, u64), String> {
+    // The size of the transaction does not depend on the amount sent to `destination`.
+    let mut outputs: Vec<TxOut> = fixed_outputs.iter().map(PrimaryOutput::tx_out).collect();
+    outputs.push(TxOut {
+        value: Amount::ZERO,
+        script_pubkey: destination.script_pubkey(),
+    });
+    let vsize = predict_vsize(input, utxos.len(), &outputs);
+    let (transaction, prevouts) = build_sweep_with_fee(
+        utxos,
+        own_address,
+        fixed_outputs,
+        destination,
+        fee(vsize, fee_per_byte),
+    )?;
+    Ok((transaction, prevouts, vsize))
+}
+
+/// Builds a transaction like [`build_sweep_with_predicted_fee`] that pays exactly `fee`.
+pub fn build_sweep_with_fee(
+    utxos: &[Utxo],
+    own_address: &Address,
+    fixed_outputs: &[PrimaryOutput],
+    destination: &Address,
+    fee: u64,
+) -> Result<(Transaction, Vec<TxOut>), String> {
+    let total: u64 = utxos.iter().map(|utxo| utxo.value).sum();
+    let fixed: u64 = fixed_outputs.iter().map(PrimaryOutput::amount).sum();
+    let amount = total
+        .checked_sub(fixed + fee)
+        .ok_or_else(|| format!("Balance of {} satoshi does not cover the fee", total))?;
+    let dust_limit = dust_limit(destination);
+    if amount < dust_limit {
+        return Err(format!(
+            "Balance too small to sweep: {} satoshi would be left after the fee of {} satoshi, \
+             below the dust limit of {} satoshi of the destination",
+            amount, fee, dust_limit
+        ));
+    }
+    build_transaction_with_fee(
+        utxos.iter().collect(),
+        own_address,
+        &[
+            fixed_outputs,
+            &[PrimaryOutput::Address(destination.clone(), amount)],
+        ]
+        .concat(),
+        fee,
+    )
+}
+
+/// How soon a transaction should confirm, which determines the percentile of recent fee
+/// rates it pays.
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FeePriority {
+    /// The 25th percentile, for payments that can wait, such as consolidations.
+    #[serde(rename = "economy")]
+    Economy,
+    /// The median.
+    #[default]
+    #[serde(rename = "normal")]
+    Normal,
+    /// The 90th percentile, for time-sensitive payments.
+    #[serde(rename = "priority")]
+    Priority,
+}
+
+impl FeePriority {
+    fn percentile(self) -> usize {
+        match self {
+            FeePriority::Economy => 25,
+            FeePriority::Normal => 50,
+            FeePriority::Priority => 90,
+        }
+    }
+}
+
+/// Sanity limits on the fee rate of every transaction, in satoshi per virtual byte.
+///
+/// Fee rates estimated from recent transactions are clamped to these limits, and fee rates
+/// given explicitly must lie within them. Controllers of the smart contract can change them.
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FeeRateLimits {
+    pub min_sat_per_vbyte: u64,
+    pub max_sat_per_vbyte: u64,
+}
+
+impl Default for FeeRateLimits {
+    fn default() -> Self {
+        Self {
+            // The minimum relay fee of Bitcoin Core.
+            min_sat_per_vbyte: 1,
+            max_sat_per_vbyte: 1_000,
+        }
+    }
+}
+
+impl FeeRateLimits {
+    /// Clamps `fee_per_byte`, in millisatoshis per byte, to the limits.
+    pub fn clamp(&self, fee_per_byte: MillisatoshiPerByte) -> MillisatoshiPerByte {
+        fee_per_byte.clamp(self.min_sat_per_vbyte * 1000, self.max_sat_per_vbyte * 1000)
+    }
+
+    /// Converts a fee rate given in satoshi per virtual byte to millisatoshis per byte,
+    /// provided it lies within the limits.
+    pub fn check(&self, sat_per_vbyte: u64) -> Result<MillisatoshiPerByte, String> {
+        if sat_per_vbyte < self.min_sat_per_vbyte || sat_per_vbyte > self.max_sat_per_vbyte {
+            return Err(format!(
+                "Fee rate must be between {} and {} sat/vB",
+                self.min_sat_per_vbyte, self.max_sat_per_vbyte
+            ));
+        }
+        Ok(sat_per_vbyte * 1000)
+    }
+}
+
 /// Estimates a reasonable fee rate for Bitcoin transactions based on network conditions.
 ///
-/// This function queries the Bitcoin network for recent fee percentiles and returns
-/// the median (50th percentile) fee rate, which provides a good balance between
-/// confirmation time and cost. The fee rate is returned in millisatoshis per byte.
+/// This is the fee rate for [`FeePriority::Normal`], see [`get_fee_per_byte_for`].
+///
+/// # Returns
+/// Fee rate in millisatoshis per byte (1,000 msat = 1 satoshi).
+pub async fn get_fee_per_byte(ctx: &BitcoinContext) -> u64 {
+    get_fee_per_byte_for(ctx, FeePriority::Normal).await
+}
+
+/// Estimates the fee rate for a transaction of the given priority based on network conditions.
+///
+/// This function queries the Bitcoin network for recent fee percentiles and returns the
+/// percentile matching the priority, clamped to the [`FeeRateLimits`]. The median provides a
+/// good balance between confirmation time and cost.
 ///
 /// On regtest networks (local development), fee data is typically unavailable since
 /// there are no standard transactions, so the function falls back to a static rate
@@ -204,7 +380,7 @@ pub fn build_transaction_with_fee(
 ///
 /// # Returns
 /// Fee rate in millisatoshis per byte (1,000 msat = 1 satoshi).
-pub async fn get_fee_per_byte(ctx: &BitcoinContext) -> u64 {
+pub async fn get_fee_per_byte_for(ctx: &BitcoinContext, priority: FeePriority) -> u64 {
     // Query recent fee percentiles from the Bitcoin network.
     // This gives us real-time fee data based on recent transaction activity.
     let fee_percentiles = bitcoin_get_current_fee_percentiles(&GetCurrentFeePercentilesRequest {
@@ -213,13 +389,13 @@ pub async fn get_fee_per_byte(ctx: &BitcoinContext) -> u64 {
     .await
     .unwrap();
 
-    if fee_percentiles.is_empty() {
+    let fee_per_byte = if fee_percentiles.is_empty() {
         // Empty percentiles indicate that we're likely on regtest with no standard transactions.
         // Use a reasonable fallback that works for development and testing.
         2000 // 2 sat/vB in millisatoshis
     } else {
-        // Use the 50th percentile (median) for balanced confirmation time and cost.
-        // This avoids both overpaying (high percentiles) and slow confirmation (low percentiles).
-        fee_percentiles[50]
-    }
+        // Higher percentiles confirm faster, lower ones are cheaper.
+        fee_percentiles[priority.percentile()]
+    };
+    read_state(|state| state.fee_rate_limits).clamp(fee_per_byte)
 }
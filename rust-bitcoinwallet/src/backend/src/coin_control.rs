@@ -0,0 +1,93 @@
+// This module keeps the notes principals attach to their own outputs: labels, e.g. where the
+// funds came from, and freezes, which keep an output out of coin selection until it is
+// unfrozen. Both are keyed by outpoint and outlive the output, so an output can be labeled or
+// frozen before it is confirmed.
+//
+// Frozen outputs are skipped by coin selection, like protected ones (see `crate::protection`),
+// and unlike those they cannot be spent deliberately either: their owner has decided not to
+// spend them until they are unfrozen.
+
+use bitcoin::OutPoint;
+use candid::Principal;
+
+use crate::{
+    protection::is_protected,
+    state::{mutate_state, read_state},
+};
+
+/// Upper bound on the number of outputs a principal can label, and on the number of outputs
+/// they can freeze. Labels and freezes outlive the outputs, so they have to be removed to make
+/// room for new ones.
+pub const MAX_NOTED_OUTPUTS: usize = 1_000;
+
+/// Sets the label of the output at `outpoint` of `owner`. An empty label removes it.
+pub fn label_output(owner: Principal, outpoint: OutPoint, label: String) -> Result<(), String> {
+    mutate_state(|state| {
+        let labels = state.utxo_labels.entry(owner).or_default();
+        if label.is_empty() {
+            labels.remove(&outpoint.to_string());
+        } else {
+            if labels.len() >= MAX_NOTED_OUTPUTS && !labels.contains_key(&outpoint.to_string()) {
+                return Err(format!(
+                    "Cannot label more than {} outputs; remove some labels first",
+                    MAX_NOTED_OUTPUTS
+                ));
+            }
+            labels.insert(outpoint.to_string(), label);
+        }
+        Ok(())
+    })
+}
+
+/// The label of the output at `outpoint` of `owner`, if it has one.
+pub fn output_label(owner: Principal, outpoint: &OutPoint) -> Option<String> {
+    read_state(|state| {
+        state
+            .utxo_labels
+            .get(&owner)
+            .and_then(|labels| labels.get(&outpoint.to_string()).cloned())
+    })
+}
+
+/// Freezes the output at `outpoint` of `owner`, which keeps it from being spent.
+pub fn freeze_output(owner: Principal, outpoint: OutPoint) -> Result<(), String> {
+    mutate_state(|state| {
+        let frozen_outputs = state.frozen_outputs.entry(owner).or_default();
+        if frozen_outputs.len() >= MAX_NOTED_OUTPUTS
+            && !frozen_outputs.contains(&outpoint.to_string())
+        {
+            return Err(format!(
+                "Cannot freeze more than {} outputs; unfreeze some first",
+                MAX_NOTED_OUTPUTS
+            ));
+        }
+        frozen_outputs.insert(outpoint.to_string());
+        Ok(())
+    })
+}
+
+/// Unfreezes the output at `outpoint` of `owner`.
+pub fn unfreeze_output(owner: Principal, outpoint: OutPoint) {
+    mutate_state(|state| {
+        if let Some(frozen_outputs) = state.frozen_outputs.get_mut(&owner) {
+            frozen_outputs.remove(&outpoint.to_string());
+        }
+    });
+}
+
+/// Whether the output at `outpoint` of `owner` is frozen.
+pub fn is_frozen(owner: Principal, outpoint: &OutPoint) -> bool {
+    let outpoint = outpoint.to_string();
+    read_state(|state| {
+        state
+            .frozen_outputs
+            .get(&owner)
+            .is_some_and(|frozen_outputs| frozen_outputs.contains(&outpoint))
+    })
+}
+
+/// Whether the output at `outpoint` of `owner` may fund payments and fees: it is neither
+/// protected nor frozen.
+pub fn is_spendable(owner: Principal, outpoint: &OutPoint) -> bool {
+    !is_protected(owner, outpoint) && !is_frozen(owner, outpoint)
+}
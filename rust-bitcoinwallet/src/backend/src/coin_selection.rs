@@ -0,0 +1,434 @@
+// This module decides which UTXOs fund a transaction.
+//
+// UTXOs are weighed by their effective value: their value minus the fee for spending them at
+// the current fee rate. UTXOs whose effective value is not positive cost more to spend than
+// they are worth and are left alone. A selection whose excess over the payment and fee is too
+// small to be worth a change output adds the excess to the fee instead of creating change.
+//
+// Candidate selections are compared by their waste, as in Bitcoin Core: the extra fee paid for
+// spending the inputs now rather than at a long-term fee rate, plus the cost of the change
+// output, or the excess given up to the fee if there is none. Selections with less waste are
+// cheaper in the long run.
+
+use bitcoin::{transaction::InputWeightPrediction, Address, Amount, TxOut};
+use candid::{CandidType, Deserialize};
+use ic_cdk::bitcoin_canister::{MillisatoshiPerByte, Utxo};
+
+use crate::{btc::PrimaryOutput, vsize::predict_vsize};
+
+/// The fee rate at which UTXOs are expected to be spent in the long run, used to weigh
+/// spending them now against spending them later.
+const LONG_TERM_FEE_PER_BYTE: MillisatoshiPerByte = 10_000;
+
+/// Upper bound on the number of combinations branch-and-bound tries.
+const MAX_BNB_TRIES: usize = 100_000;
+
+/// What a selection has to pay for, with the sizes of the parts of the transaction that
+/// depend on it.
+#[derive(Clone, Copy)]
+pub struct SelectionParams {
+    /// The sum of the primary outputs.
+    pub amount: u64,
+    pub fee_per_byte: MillisatoshiPerByte,
+    /// How the inputs to select are signed.
+    pub input: InputWeightPrediction,
+    /// Virtual size of the transaction without inputs and change output.
+    pub base_vsize: u64,
+    /// Virtual size each signed input adds.
+    pub input_vsize: u64,
+    /// Virtual size the change output adds.
+    pub change_vsize: u64,
+}
+
+impl SelectionParams {
+    /// Predicts the parts of a transaction paying `primary_outputs` with change to
+    /// `own_address`, whose inputs are signed like `input`.
+    pub fn new(
+        primary_outputs: &[PrimaryOutput],
+        own_address: &Address,
+        input: InputWeightPrediction,
+        fee_per_byte: MillisatoshiPerByte,
+    ) -> Self {
+        let change = TxOut {
+            value: Amount::ZERO,
+            script_pubkey: own_address.script_pubkey(),
+        };
+        Self {
+            amount: primary_outputs.iter().map(PrimaryOutput::amount).sum(),
+            fee_per_byte,
+            input,
+            base_vsize: predict_vsize(
+                input,
+                0,
+                &primary_outputs
+                    .iter()
+                    .map(PrimaryOutput::tx_out)
+                    .collect::<Vec<_>>(),
+            ),
+            // This counts the segregated witness marker and flag with every input, which
+            // keeps the estimate on the safe side.
+            input_vsize: predict_vsize(input, 1, []) - predict_vsize(input, 0, []),
+            change_vsize: change.weight().to_vbytes_ceil(),
+        }
+    }
+
+    /// The fee for `vsize` virtual bytes at the current fee rate, rounded up.
+    pub fn fee(&self, vsize: u64) -> u64 {
+        (vsize * self.fee_per_byte).div_ceil(1000)
+    }
+
+    /// The fee for spending one input.
+    pub fn input_fee(&self) -> u64 {
+        self.fee(self.input_vsize)
+    }
+
+    /// The value of `utxo` minus the fee for spending it, or 0 if it does not cover the fee.
+    pub fn effective_value(&self, utxo: &Utxo) -> u64 {
+        utxo.value.saturating_sub(self.input_fee())
+    }
+
+    /// The amount the effective values of the selected UTXOs have to cover: the primary
+    /// outputs plus the fee for the rest of the transaction, without change.
+    pub fn target(&self) -> u64 {
+        self.amount + self.fee(self.base_vsize)
+    }
+
+    /// The cost of a change output: the fee for creating it now and for spending it later.
+    pub fn cost_of_change(&self) -> u64 {
+        self.fee(self.change_vsize) + (self.input_vsize * LONG_TERM_FEE_PER_BYTE).div_ceil(1000)
+    }
+
+    /// The waste of spending `utxos`, which must cover the target. Negative when spending the
+    /// inputs now is cheaper than at the long-term fee rate.
+    pub fn waste(&self, utxos: &[&Utxo]) -> i64 {
+        let long_term_input_fee = (self.input_vsize * LONG_TERM_FEE_PER_BYTE).div_ceil(1000);
+        let timing = utxos.len() as i64 * (self.input_fee() as i64 - long_term_input_fee as i64);
+        let excess = self.excess(utxos);
+        if excess > self.cost_of_change() {
+            timing + self.cost_of_change() as i64
+        } else {
+            timing + excess as i64
+        }
+    }
+
+    // The effective value of `utxos` beyond the target.
+    fn excess(&self, utxos: &[&Utxo]) -> u64 {
+        let total: u64 = utxos.iter().map(|utxo| self.effective_value(utxo)).sum();
+        total.saturating_sub(self.target())
+    }
+
+    fn insufficient_balance(&self, available: u64) -> String {
+        format!(
+            "Insufficient balance: {} after fees, trying to transfer {} satoshi with fee {}",
+            available,
+            self.amount,
+            self.target() - self.amount
+        )
+    }
+}
+
+/// UTXOs chosen to fund a transaction.
+pub struct Selection<'a> {
+    pub utxos: Vec<&'a Utxo>,
+    /// Whether the excess is worth a change output. Otherwise it goes to the fee.
+    pub change: bool,
+}
+
+impl<'a> Selection<'a> {
+    /// Decides whether `utxos`, which must cover the target, get a change output.
+    pub fn new(utxos: Vec<&'a Utxo>, params: &SelectionParams) -> Self {
+        let change = params.excess(&utxos) > params.cost_of_change();
+        Self { utxos, change }
+    }
+
+    /// The fee to build the transaction with, given the fee estimated from its size. Without
+    /// change, the whole excess goes to the fee.
+    pub fn fee(&self, params: &SelectionParams, estimated_fee: u64) -> u64 {
+        if self.change {
+            estimated_fee
+        } else {
+            let total: u64 = self.utxos.iter().map(|utxo| utxo.value).sum();
+            total.saturating_sub(params.amount).max(estimated_fee)
+        }
+    }
+}
+
+/// A strategy for choosing the UTXOs that fund a transaction.
+pub trait CoinSelection {
+    /// Selects UTXOs among `utxos` whose effective values cover the target of `params`.
+    fn select<'a>(
+        &self,
+        utxos: &'a [Utxo],
+        params: &SelectionParams,
+    ) -> Result<Selection<'a>, String>;
+}
+
+/// The coin selection strategies callers can choose from, see [`CoinSelection`].
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CoinSelectionStrategy {
+    /// Searches for a combination of UTXOs that needs no change output, falling back to the
+    /// other strategies' selection with the least waste.
+    #[default]
+    #[serde(rename = "branch_and_bound")]
+    BranchAndBound,
+    /// Spends the UTXOs with the largest values first, which keeps transactions small.
+    #[serde(rename = "largest_first")]
+    LargestFirst,
+    /// Spends the UTXOs that were confirmed first, which consolidates old outputs.
+    #[serde(rename = "oldest_first")]
+    OldestFirst,
+    /// Spends as few UTXOs as possible, ideally one, which links as few of the account's
+    /// outputs on-chain as possible.
+    #[serde(rename = "privacy")]
+    Privacy,
+}
+
+impl CoinSelection for CoinSelectionStrategy {
+    fn select<'a>(
+        &self,
+        utxos: &'a [Utxo],
+        params: &SelectionParams,
+    ) -> Result<Selection<'a>, String> {
+        match self {
+            CoinSelectionStrategy::BranchAndBound => BranchAndBound.select(utxos, params),
+            CoinSelectionStrategy::LargestFirst => LargestFirst.select(utxos, params),
+            CoinSelectionStrategy::OldestFirst => OldestFirst.select(utxos, params),
+            CoinSelectionStrategy::Privacy => Privacy.select(utxos, params),
+        }
+    }
+}
+
+/// Branch-and-bound search for a changeless selection, as in Bitcoin Core.
+///
+/// Explores combinations of UTXOs, largest effective value first, whose excess over the
+/// target is at most the cost of a change output, and keeps the one with the least waste.
+/// If there is none, falls back to the selection of [`LargestFirst`] or [`OldestFirst`],
+/// whichever wastes less.
+pub struct BranchAndBound;
+
+impl CoinSelection for BranchAndBound {
+    fn select<'a>(
+        &self,
+        utxos: &'a [Utxo],
+        params: &SelectionParams,
+    ) -> Result<Selection<'a>, String> {
+        let mut candidates = economical_utxos(utxos, params);
+        candidates.sort_by_key(|utxo| std::cmp::Reverse(params.effective_value(utxo)));
+        let values: Vec<u64> = candidates
+            .iter()
+            .map(|utxo| params.effective_value(utxo))
+            .collect();
+
+        let target = params.target();
+        let upper_bound = target + params.cost_of_change();
+
+        // Depth-first search over the inclusion of each candidate. `remaining[i]` is the sum of
+        // the values from `i` on, which bounds what the rest of a branch can add.
+        let mut remaining = vec![0; values.len() + 1];
+        for i in (0..values.len()).rev() {
+            remaining[i] = remaining[i + 1] + values[i];
+        }
+        let mut best: Option<(i64, Vec<usize>)> = None;
+        let mut included: Vec<usize> = vec![];
+        let mut total = 0;
+        let mut next = 0;
+        for _ in 0..MAX_BNB_TRIES {
+            let backtrack = if total > upper_bound || total + remaining[next] < target {
+                true
+            } else if total >= target {
+                let selected: Vec<_> = included.iter().map(|i| candidates[*i]).collect();
+                let waste = params.waste(&selected);
+                if best
+                    .as_ref()
+                    .is_none_or(|(best_waste, _)| waste < *best_waste)
+                {
+                    best = Some((waste, included.clone()));
+                }
+                true
+            } else {
+                next == values.len()
+            };
+
+            if backtrack {
+                // Exclude the most recently included candidate and continue after it.
+                let Some(last) = included.pop() else {
+                    break;
+                };
+                total -= values[last];
+                next = last + 1;
+            } else {
+                included.push(next);
+                total += values[next];
+                next += 1;
+            }
+        }
+
+        match best {
+            Some((_, included)) => Ok(Selection {
+                utxos: included.into_iter().map(|i| candidates[i]).collect(),
+                change: false,
+            }),
+            None => {
+                let largest_first = LargestFirst.select(utxos, params)?;
+                let oldest_first = OldestFirst.select(utxos, params)?;
+                if params.waste(&oldest_first.utxos) < params.waste(&largest_first.utxos) {
+                    Ok(oldest_first)
+                } else {
+                    Ok(largest_first)
+                }
+            }
+        }
+    }
+}
+
+/// Spends the UTXOs with the largest effective values first.
+pub struct LargestFirst;
+
+impl CoinSelection for LargestFirst {
+    fn select<'a>(
+        &self,
+        utxos: &'a [Utxo],
+        params: &SelectionParams,
+    ) -> Result<Selection<'a>, String> {
+        let mut candidates = economical_utxos(utxos, params);
+        candidates.sort_by_key(|utxo| std::cmp::Reverse(utxo.value));
+        accumulate(candidates, params)
+    }
+}
+
+/// Spends the UTXOs with the lowest confirmation height first.
+pub struct OldestFirst;
+
+impl CoinSelection for OldestFirst {
+    fn select<'a>(
+        &self,
+        utxos: &'a [Utxo],
+        params: &SelectionParams,
+    ) -> Result<Selection<'a>, String> {
+        let mut candidates = economical_utxos(utxos, params);
+        candidates.sort_by_key(|utxo| utxo.height);
+        accumulate(candidates, params)
+    }
+}
+
+/// Spends the single UTXO that covers the target with the least waste. If no UTXO does on its
+/// own, spends as few as possible, largest first.
+pub struct Privacy;
+
+impl CoinSelection for Privacy {
+    fn select<'a>(
+        &self,
+        utxos: &'a [Utxo],
+        params: &SelectionParams,
+    ) -> Result<Selection<'a>, String> {
+        economical_utxos(utxos, params)
+            .into_iter()
+            .filter(|utxo| params.effective_value(utxo) >= params.target())
+            .min_by_key(|utxo| params.waste(&[utxo]))
+            .map(|utxo| Selection::new(vec![utxo], params))
+            .map_or_else(|| LargestFirst.select(utxos, params), Ok)
+    }
+}
+
+/// Spends all UTXOs, whatever their effective value, e.g. to move every output of an
+/// address.
+pub struct SelectAll;
+
+impl CoinSelection for SelectAll {
+    fn select<'a>(
+        &self,
+        utxos: &'a [Utxo],
+        params: &SelectionParams,
+    ) -> Result<Selection<'a>, String> {
+        let total: u64 = utxos.iter().map(|utxo| params.effective_value(utxo)).sum();
+        if total < params.target() {
+            return Err(params.insufficient_balance(total));
+        }
+        Ok(Selection::new(utxos.iter().collect(), params))
+    }
+}
+
+/// Spends all `required` UTXOs first, in order and whatever their effective value, e.g.
+/// because they carry an inscription or runes, topped up by `then` from the other UTXOs if
+/// needed.
+///
+/// The required UTXOs must be among the UTXOs passed to [`CoinSelection::select`].
+pub struct Required<'r> {
+    pub required: &'r [Utxo],
+    pub then: &'r dyn CoinSelection,
+}
+
+impl CoinSelection for Required<'_> {
+    fn select<'a>(
+        &self,
+        utxos: &'a [Utxo],
+        params: &SelectionParams,
+    ) -> Result<Selection<'a>, String> {
+        let mut selected = vec![];
+        for required in self.required {
+            let utxo = utxos
+                .iter()
+                .find(|utxo| utxo.outpoint == required.outpoint)
+                .ok_or("Required UTXO is not available")?;
+            selected.push(utxo);
+        }
+        let others: Vec<Utxo> = utxos
+            .iter()
+            .filter(|utxo| !self.required.iter().any(|r| r.outpoint == utxo.outpoint))
+            .cloned()
+            .collect();
+
+        // The required UTXOs may be uneconomical, so they pay their own spending fee, and the
+        // others only need to cover what is left.
+        let required_value: u64 = selected.iter().map(|utxo| utxo.value).sum();
+        let required_fee = selected.len() as u64 * params.input_fee();
+        if required_value < params.target() + required_fee {
+            let rest = SelectionParams {
+                amount: (params.amount + required_fee).saturating_sub(required_value),
+                ..*params
+            };
+            for utxo in self.then.select(&others, &rest)?.utxos {
+                selected.extend(utxos.iter().find(|u| u.outpoint == utxo.outpoint));
+            }
+        }
+
+        let change = required_value
+            + selected[self.required.len()..]
+                .iter()
+                .map(|utxo| params.effective_value(utxo))
+                .sum::<u64>()
+            > params.target() + required_fee + params.cost_of_change();
+        Ok(Selection {
+            utxos: selected,
+            change,
+        })
+    }
+}
+
+// The UTXOs whose effective value is positive, in their original order.
+fn economical_utxos<'a>(utxos: &'a [Utxo], params: &SelectionParams) -> Vec<&'a Utxo> {
+    utxos
+        .iter()
+        .filter(|utxo| params.effective_value(utxo) > 0)
+        .collect()
+}
+
+// Takes `candidates` in order until their effective values cover the target.
+fn accumulate<'a>(
+    candidates: Vec<&'a Utxo>,
+    params: &SelectionParams,
+) -> Result<Selection<'a>, String> {
+    let mut selected = vec![];
+    let mut total = 0;
+    for utxo in candidates {
+        if total >= params.target() {
+            break;
+        }
+        total += params.effective_value(utxo);
+        selected.push(utxo);
+    }
+    if total < params.target() {
+        return Err(params.insufficient_balance(total));
+    }
+    Ok(Selection::new(selected, params))
+}
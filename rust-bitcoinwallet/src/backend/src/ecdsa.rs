@@ -0,0 +1,84 @@
+use std::{cell::RefCell, collections::HashMap};
+
+use crate::BitcoinContext;
+use ic_cdk::management_canister::{
+    self, EcdsaCurve, EcdsaKeyId, EcdsaPublicKeyArgs, SignWithEcdsaArgs,
+};
+
+type DerivationPath = Vec<Vec<u8>>;
+type EcdsaKey = Vec<u8>;
+
+// In-memory cache for ECDSA public keys. Note: this cache is not persistent across smart contract upgrades.
+thread_local! {
+    static ECDSA_KEY_CACHE: RefCell<HashMap<DerivationPath, EcdsaKey>> = RefCell::new(HashMap::new());
+}
+
+/// Retrieves the ECDSA public key for the given derivation path from the ECDSA API.
+///
+/// This function checks the local in-memory cache first. If no cached key exists,
+/// it queries the ECDSA API for the public key at the given derivation path
+/// and stores the result in the cache. The key is returned in SEC1 compressed form.
+pub async fn get_ecdsa_public_key(ctx: &BitcoinContext, derivation_path: Vec<Vec<u8>>) -> Vec<u8> {
+    // Retrieve and return already stored public key
+    if let Some(key) = ECDSA_KEY_CACHE.with_borrow(|map| map.get(&derivation_path).cloned()) {
+        return key;
+    }
+
+    let public_key = management_canister::ecdsa_public_key(&EcdsaPublicKeyArgs {
+        canister_id: None,
+        derivation_path: derivation_path.clone(),
+        key_id: EcdsaKeyId {
+            name: ctx.key_name.to_string(),
+            curve: EcdsaCurve::Secp256k1,
+        },
+    })
+    .await
+    .unwrap()
+    .public_key;
+
+    // Cache the public key
+    ECDSA_KEY_CACHE.with_borrow_mut(|map| {
+        map.insert(derivation_path, public_key.clone());
+    });
+
+    public_key
+}
+
+/// Returns the ECDSA signature of `message_hash` in compact (64-byte `r || s`) form.
+/// The hash will be signed with the private key derived from `key_name` and
+/// `derivation_path`.
+pub async fn sign_with_ecdsa(
+    key_name: String,
+    derivation_path: Vec<Vec<u8>>,
+    message_hash: Vec<u8>,
+) -> Vec<u8> {
+    management_canister::sign_with_ecdsa(&SignWithEcdsaArgs {
+        message_hash,
+        derivation_path,
+        key_id: EcdsaKeyId {
+            name: key_name,
+            curve: EcdsaCurve::Secp256k1,
+        },
+    })
+    .await
+    .unwrap()
+    .signature
+}
+
+/// Returns a mock ECDSA signature used solely for **transaction size estimation**.
+///
+/// The returned compact signature parses as a valid ECDSA signature whose `r`
+/// value has its high bit set, so its DER encoding has the maximum length a
+/// low-S signature can have. This keeps fee estimates on the safe side.
+///
+/// # Safety
+/// Do not broadcast transactions signed with this signature.
+pub async fn mock_sign_with_ecdsa(
+    _key_name: String,
+    _derivation_path: Vec<Vec<u8>>,
+    _message_hash: Vec<u8>,
+) -> Vec<u8> {
+    let mut signature = vec![0x80; 32];
+    signature.extend_from_slice(&[0x01; 32]);
+    signature
+}
@@ -0,0 +1,255 @@
+// This module keeps the payments the smart contract sent on behalf of accounts, so that they
+// can be replaced while they are unconfirmed (BIP-125). Every input is signed with a sequence
+// number that signals replaceability, see e.g. `crate::p2tr::sign_transaction_key_spend`.
+//
+// Payments are forgotten once they are replaced or seen confirmed, and only the latest ones of
+// each principal are kept, so that the state does not grow without bound.
+//
+// The Bitcoin API of the Internet Computer only knows confirmed transactions. The outputs a
+// payment spends therefore remain among the UTXOs of the account until the payment, or a
+// replacement, is confirmed, which tells whether a payment can still be replaced.
+
+use std::collections::BTreeSet;
+
+use bitcoin::{
+    consensus::{deserialize, serialize},
+    OutPoint, Transaction, TxOut, Txid,
+};
+use candid::{CandidType, Deserialize, Principal};
+
+use ic_cdk::bitcoin_canister::Utxo;
+
+use crate::{
+    account::{Account, AccountId, AddressType},
+    btc::{outpoint, transaction_fee, BitcoinContext},
+    coin_control::is_spendable,
+    state::{mutate_state, read_state},
+};
+
+/// Upper bound on the number of payments remembered per principal. Beyond it, the oldest
+/// ones are forgotten first.
+pub const MAX_SENT_TRANSACTIONS: usize = 100;
+
+/// The increase in fee rate a replacement has to pay for its own size, in satoshi per virtual
+/// byte, as required by the default relay policy of Bitcoin Core.
+pub const INCREMENTAL_RELAY_FEE: u64 = 1;
+
+/// A payment sent from an account.
+#[derive(CandidType, Deserialize, Clone)]
+pub struct SentTransaction {
+    pub owner: Principal,
+    pub account_id: AccountId,
+    pub address_type: AddressType,
+    /// The signed transaction, consensus-encoded.
+    pub transaction: Vec<u8>,
+    /// The fee in satoshi.
+    pub fee: u64,
+    /// The number of leading outputs that pay someone, the rest is change. Zero for a sweep,
+    /// whose only output receives whatever the fee leaves.
+    pub payments: u32,
+    /// When the payment was sent, in nanoseconds since the epoch.
+    pub sent_at: u64,
+    /// The outputs of unconfirmed transactions it spends, if it is a child paying for its
+    /// parent, see `cpfp`.
+    pub unconfirmed_inputs: Vec<Utxo>,
+}
+
+impl SentTransaction {
+    pub fn transaction(&self) -> Transaction {
+        deserialize(&self.transaction).expect("recorded transactions are valid")
+    }
+}
+
+/// Records that `account` sent `transaction`, spending `prevouts`, whose first `payments`
+/// outputs pay someone.
+pub fn record_sent_transaction(
+    account: &Account,
+    transaction: &Transaction,
+    prevouts: &[TxOut],
+    payments: usize,
+) {
+    record(account, transaction, prevouts, payments, vec![]);
+}
+
+/// Records that `account` sent `transaction`, spending `prevouts`, which sweeps the outputs
+/// `parent_utxos` of unconfirmed parents, see `cpfp`.
+pub fn record_child_transaction(
+    account: &Account,
+    transaction: &Transaction,
+    prevouts: &[TxOut],
+    parent_utxos: Vec<Utxo>,
+) {
+    record(account, transaction, prevouts, 0, parent_utxos);
+}
+
+fn record(
+    account: &Account,
+    transaction: &Transaction,
+    prevouts: &[TxOut],
+    payments: usize,
+    unconfirmed_inputs: Vec<Utxo>,
+) {
+    let sent_transaction = SentTransaction {
+        owner: account.principal,
+        account_id: account.account_id,
+        address_type: account.address_type,
+        transaction: serialize(transaction),
+        fee: transaction_fee(transaction, prevouts),
+        payments: payments as u32,
+        sent_at: ic_cdk::api::time(),
+        unconfirmed_inputs,
+    };
+    mutate_state(|state| {
+        let owned: Vec<_> = state
+            .sent_transactions
+            .iter()
+            .filter(|(_, sent_transaction)| sent_transaction.owner == account.principal)
+            .map(|(txid, sent_transaction)| (sent_transaction.sent_at, txid.clone()))
+            .collect();
+        if owned.len() >= MAX_SENT_TRANSACTIONS {
+            let (_, oldest) = owned.into_iter().min().expect("at least one payment");
+            state.sent_transactions.remove(&oldest);
+        }
+        state
+            .sent_transactions
+            .insert(transaction.compute_txid().to_string(), sent_transaction)
+    });
+}
+
+/// Forgets the transaction `txid` sent by `owner`, once it is replaced or confirmed.
+pub fn forget_transaction(owner: Principal, txid: Txid) {
+    mutate_state(|state| {
+        if state
+            .sent_transactions
+            .get(&txid.to_string())
+            .is_some_and(|sent_transaction| sent_transaction.owner == owner)
+        {
+            state.sent_transactions.remove(&txid.to_string());
+        }
+    });
+}
+
+/// The transaction `txid` sent by `owner`, provided it is remembered: it has been neither
+/// replaced nor seen confirmed.
+pub fn replaceable_transaction(owner: Principal, txid: Txid) -> Result<SentTransaction, String> {
+    read_state(|state| state.sent_transactions.get(&txid.to_string()).cloned())
+        .filter(|sent_transaction| sent_transaction.owner == owner)
+        .ok_or_else(|| {
+            format!(
+                "No unconfirmed transaction {} sent by the caller that was not replaced",
+                txid
+            )
+        })
+}
+
+/// Returns the UTXOs `original` spends, in order, followed by the other UTXOs of `account` that
+/// may fund a replacement. Fails once `original` is confirmed, and forgets it.
+///
+/// UTXOs spent by other unconfirmed payments of the owner are left out, since spending them
+/// would evict those payments as well.
+pub async fn replaceable_inputs(
+    ctx: &BitcoinContext,
+    account: &mut Account,
+    original: &SentTransaction,
+) -> Result<(Vec<Utxo>, Vec<Utxo>), String> {
+    let own_utxos = account.get_utxos(ctx).await?;
+    let transaction = original.transaction();
+    let txid = transaction.compute_txid();
+
+    // Until the original payment is confirmed, the outputs it spends are still unspent, unless
+    // they are outputs of an unconfirmed parent. Once it is confirmed, its change shows up.
+    let mut original_utxos = vec![];
+    let mut confirmed = own_utxos.iter().any(|utxo| outpoint(utxo).txid == txid);
+    for input in transaction.input.iter() {
+        match own_utxos
+            .iter()
+            .chain(&original.unconfirmed_inputs)
+            .find(|utxo| outpoint(utxo) == input.previous_output)
+        {
+            Some(utxo) => original_utxos.push(utxo.clone()),
+            None => confirmed = true,
+        }
+    }
+    if confirmed {
+        forget_transaction(account.principal, txid);
+        return Err(format!("Transaction {} is already confirmed", txid));
+    }
+    let pending_inputs = pending_inputs(account.principal, txid);
+    let other_utxos = own_utxos
+        .into_iter()
+        .filter(|utxo| {
+            !original_utxos.contains(utxo)
+                && !pending_inputs.contains(&outpoint(utxo))
+                && is_spendable(account.principal, &outpoint(utxo))
+        })
+        .collect();
+    Ok((original_utxos, other_utxos))
+}
+
+// The outpoints spent by the remembered payments of `owner`, except the transaction `except`.
+// Those that are confirmed no longer spend any UTXO, so they do no harm.
+fn pending_inputs(owner: Principal, except: Txid) -> BTreeSet<OutPoint> {
+    let except = except.to_string();
+    read_state(|state| {
+        state
+            .sent_transactions
+            .iter()
+            .filter(|(txid, sent_transaction)| **txid != except && sent_transaction.owner == owner)
+            .flat_map(|(_, sent_transaction)| {
+                sent_transaction
+                    .transaction()
+                    .input
+                    .into_iter()
+                    .map(|input| input.previous_output)
+            })
+            .collect()
+    })
+}
+
+/// The fees of the remembered children of the transaction `txid` sent by `owner`, see `cpfp`.
+/// A replacement evicts them as well and has to pay for them.
+pub fn descendant_fee(owner: Principal, txid: Txid) -> u64 {
+    read_state(|state| {
+        state
+            .sent_transactions
+            .values()
+            .filter(|sent_transaction| {
+                sent_transaction.owner == owner
+                    && sent_transaction
+                        .unconfirmed_inputs
+                        .iter()
+                        .any(|utxo| outpoint(utxo).txid == txid)
+            })
+            .map(|sent_transaction| sent_transaction.fee)
+            .sum()
+    })
+}
+
+/// The lowest fee a replacement of `original` of `vsize` virtual bytes may pay under the fee
+/// rules of BIP-125: it pays a higher fee rate, and its fee exceeds the fees of the original
+/// and its children by at least the incremental relay fee for its own size.
+pub fn min_replacement_fee(original: &SentTransaction, vsize: u64) -> u64 {
+    let original_transaction = original.transaction();
+    let original_vsize = original_transaction.vsize() as u64;
+    let descendant_fee = descendant_fee(original.owner, original_transaction.compute_txid());
+    (original.fee + descendant_fee + vsize * INCREMENTAL_RELAY_FEE)
+        .max(original.fee * vsize / original_vsize + 1)
+}
+
+/// Checks that a replacement of `original` paying `fee` for `vsize` virtual bytes satisfies
+/// the fee rules of BIP-125, see [`min_replacement_fee`].
+pub fn check_replacement_fee(
+    original: &SentTransaction,
+    fee: u64,
+    vsize: u64,
+) -> Result<(), String> {
+    let min_fee = min_replacement_fee(original, vsize);
+    if fee < min_fee {
+        return Err(format!(
+            "The replacement pays {} satoshi for {} vB, but must pay at least {} satoshi to \
+             replace the original",
+            fee, vsize, min_fee
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,137 @@
+// This module implements hash time-locked contracts (HTLCs) for atomic swaps.
+//
+// An HTLC is a policy account without a key path and with two leaves: the counterparty can
+// spend with the preimage of the payment hash, and the owner can take the funds back in any
+// block after the timeout height. The counterparty of a swap usually lives outside the
+// Internet Computer, so it signs with a key of its own wallet and spends the claim leaf
+// itself. The owner signs the refund with its threshold key.
+
+use bitcoin::{consensus::serialize, Address};
+use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::bitcoin_canister::{
+    bitcoin_get_utxos, bitcoin_send_transaction, GetUtxosRequest, SendTransactionRequest,
+};
+
+use crate::{
+    btc::get_fee_per_byte,
+    p2tr,
+    policy::{Policy, PolicyAccount},
+    schnorr::sign_with_schnorr,
+    BitcoinContext,
+};
+
+/// The leaf the counterparty spends through, with the preimage of the payment hash.
+pub const CLAIM_LEAF: u32 = 0;
+
+/// The leaf the owner spends through once the timeout has been passed.
+pub const REFUND_LEAF: u32 = 1;
+
+/// A hash time-locked contract between the owner and a counterparty.
+#[derive(CandidType, Deserialize, Clone)]
+pub struct Htlc {
+    /// The principal that created the HTLC and gets refunded after the timeout.
+    pub owner: Principal,
+    /// The 32-byte x-only public key that can claim the funds with the preimage of
+    /// `payment_hash`.
+    pub counterparty_key: Vec<u8>,
+    /// SHA-256 hash of the 32-byte payment preimage.
+    pub payment_hash: Vec<u8>,
+    /// Block height after which the owner can take the funds back.
+    pub timeout: u32,
+}
+
+impl Htlc {
+    /// Checks that the counterparty key, the hash and the timeout of the HTLC are valid.
+    pub fn validate(&self) -> Result<(), String> {
+        self.policy_account().validate()
+    }
+
+    /// The policy account behind the HTLC: no key path, a claim leaf
+    /// `OP_SIZE 32 OP_EQUALVERIFY OP_SHA256 <payment_hash> OP_EQUALVERIFY <counterparty> OP_CHECKSIG`
+    /// and a refund leaf `<timeout> OP_CLTV OP_DROP <owner> OP_CHECKSIG`.
+    pub fn policy_account(&self) -> PolicyAccount {
+        PolicyAccount {
+            owner: self.owner,
+            key_path_owner: None,
+            policy: Policy::Or(vec![
+                Policy::And(vec![
+                    Policy::Sha256(self.payment_hash.clone()),
+                    Policy::Key(self.counterparty_key.clone()),
+                ]),
+                Policy::And(vec![Policy::After(self.timeout), Policy::Pk(self.owner)]),
+            ]),
+        }
+    }
+}
+
+/// Sends all funds of the HTLC back to `destination` through the refund leaf, signed by the
+/// owner.
+pub async fn refund(
+    ctx: &BitcoinContext,
+    htlc: &Htlc,
+    destination: &Address,
+) -> Result<String, String> {
+    let compiled = htlc.policy_account().compile(ctx).await?;
+    let own_address = &compiled.address;
+    let script_spend = compiled.script_spend(REFUND_LEAF, &[htlc.owner], &[])?;
+
+    // Get all UTXOs for the HTLC's address.
+    // Note that pagination may have to be used to get all UTXOs for the given address.
+    // For the sake of simplicity, it is assumed here that the `utxo` field in the response
+    // contains all UTXOs.
+    let utxos_response = bitcoin_get_utxos(&GetUtxosRequest {
+        address: own_address.to_string(),
+        network: ctx.network,
+        filter: None,
+    })
+    .await
+    .map_err(|e| format!("Failed to get UTXOs: {:?}", e))?;
+
+    // The transaction can at the earliest be mined in the block after the tip.
+    let next_height = utxos_response.tip_height + 1;
+    let timelock = compiled.leaf(REFUND_LEAF)?.absolute_timelock();
+    if timelock >= next_height {
+        return Err(format!(
+            "HTLC can be refunded from block {}, current height is {}",
+            timelock + 1,
+            utxos_response.tip_height
+        ));
+    }
+
+    let own_utxos = utxos_response.utxos;
+    if own_utxos.is_empty() {
+        return Err("No UTXOs available for spending".to_string());
+    }
+
+    let fee_per_byte = get_fee_per_byte(ctx).await;
+    let (transaction, prevouts) = p2tr::build_sweep_transaction(
+        ctx,
+        own_address,
+        &own_utxos,
+        &[],
+        destination,
+        fee_per_byte,
+        Some(&script_spend),
+    )
+    .await?;
+
+    let signed_transaction = p2tr::sign_transaction_script_spend(
+        ctx,
+        own_address,
+        transaction,
+        prevouts.as_slice(),
+        &script_spend,
+        sign_with_schnorr,
+    )
+    .await;
+
+    // Send the transaction to the Bitcoin network.
+    bitcoin_send_transaction(&SendTransactionRequest {
+        network: ctx.network,
+        transaction: serialize(&signed_transaction),
+    })
+    .await
+    .map_err(|e| format!("Failed to send transaction: {:?}", e))?;
+
+    Ok(signed_transaction.compute_txid().to_string())
+}
@@ -1,12 +1,28 @@
+mod account;
 mod btc;
+mod coin_control;
+mod coin_selection;
+mod ecdsa;
+mod history;
+mod htlc;
+mod multisig;
+mod notary;
+mod ordinals;
+mod p2pkh;
 mod p2tr;
+mod p2wpkh;
+mod policy;
+mod protection;
+mod runes;
 mod schnorr;
 mod service;
+mod state;
+mod vsize;
 
 use btc::BitcoinContext;
 use candid::Principal;
 use ic_cdk::export_candid;
-use ic_cdk::{bitcoin_canister::Network, init, post_upgrade};
+use ic_cdk::{bitcoin_canister::Network, init, post_upgrade, pre_upgrade};
 
 use std::cell::Cell;
 
@@ -60,11 +76,20 @@ pub fn init(network: Network) {
     init_upgrade(network);
 }
 
+/// Pre-upgrade hook.
+/// Writes the persistent state to stable memory.
+#[pre_upgrade]
+fn pre_upgrade() {
+    state::save();
+}
+
 /// Post-upgrade hook.
-/// Reinitializes the BitcoinContext with the same logic as `init`.
+/// Reinitializes the BitcoinContext with the same logic as `init` and restores the
+/// persistent state from stable memory.
 #[post_upgrade]
 fn upgrade(network: Network) {
     init_upgrade(network);
+    state::restore();
 }
 
 /// Input structure for sending Bitcoin.
@@ -75,7 +100,25 @@ pub struct SendRequest {
     pub amount_in_satoshi: u64,
 }
 
-// Re-export SendBtcRequest for Candid interface generation
-pub use service::send_btc::SendBtcRequest;
+// Re-export types used in endpoint signatures for Candid interface generation
+pub use account::{AccountId, AddressType};
+pub use btc::{FeePriority, FeeRateLimits};
+pub use coin_selection::CoinSelectionStrategy;
+pub use multisig::{ProposalStatus, SpendProposal};
+pub use notary::Notarization;
+pub use policy::Policy;
+pub use runes::{Etching, RuneEtching, RuneId, Terms};
+pub use service::cpfp::CpfpParent;
+pub use service::get_htlc::HtlcInfo;
+pub use service::get_inheritance::InheritanceInfo;
+pub use service::get_policy_account::PolicyAccountInfo;
+pub use service::get_rune_balances::RuneBalance;
+pub use service::get_shared_wallet::SharedWalletInfo;
+pub use service::inscribe::InscriptionInfo;
+pub use service::list_accounts::AccountInfo;
+pub use service::list_utxos::UtxoInfo;
+pub use service::preview_send::{PreviewInput, PreviewOutput, SendPreview};
+pub use service::send_btc::{SendBtcRequest, SendOptions};
+pub use service::spend_policy_account::SpendPath;
 
 export_candid!();
@@ -0,0 +1,228 @@
+// This module implements k-of-n wallets shared between several principals.
+//
+// A shared wallet is a policy account without a key path whose only leaf requires signatures
+// of `threshold` of its co-owners. Spending is a two-step process: a co-owner proposes a
+// payment, and once enough co-owners have approved it, the smart contract signs the leaf with
+// the keys of the approving co-owners and broadcasts the transaction.
+
+use bitcoin::{consensus::serialize, Address};
+use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::bitcoin_canister::{
+    bitcoin_get_utxos, bitcoin_send_transaction, GetUtxosRequest, SendTransactionRequest,
+};
+use std::str::FromStr;
+
+use crate::{
+    btc::{get_fee_per_byte, PrimaryOutput},
+    coin_selection::CoinSelectionStrategy,
+    p2tr,
+    policy::{Policy, PolicyAccount},
+    schnorr::sign_with_schnorr,
+    state::{mutate_state, read_state},
+    BitcoinContext,
+};
+
+/// A wallet controlled jointly by several principals.
+#[derive(CandidType, Deserialize, Clone)]
+pub struct SharedWallet {
+    /// The principal that created the wallet.
+    pub creator: Principal,
+    pub co_owners: Vec<Principal>,
+    /// The number of co-owners that need to approve a spend.
+    pub threshold: u32,
+}
+
+#[derive(CandidType, Deserialize, Clone, PartialEq, Eq)]
+pub enum ProposalStatus {
+    /// Waiting for approvals.
+    #[serde(rename = "pending")]
+    Pending,
+    /// The transaction is being signed and broadcast.
+    #[serde(rename = "executing")]
+    Executing,
+    /// The transaction with the given id has been broadcast.
+    #[serde(rename = "executed")]
+    Executed(String),
+}
+
+/// A payment out of a shared wallet, awaiting approval by its co-owners.
+#[derive(CandidType, Deserialize, Clone)]
+pub struct SpendProposal {
+    pub id: u64,
+    pub wallet_id: u64,
+    pub destination_address: String,
+    pub amount_in_satoshi: u64,
+    /// The co-owners that approved the proposal, in order of approval.
+    pub approvals: Vec<Principal>,
+    pub status: ProposalStatus,
+}
+
+impl SharedWallet {
+    /// Checks that the wallet is a valid k-of-n setup.
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.co_owners.contains(&self.creator) {
+            return Err("The creator must be one of the co-owners".to_string());
+        }
+        if self.co_owners.contains(&Principal::anonymous()) {
+            return Err("Co-owners must not be anonymous".to_string());
+        }
+        self.policy_account().validate()
+    }
+
+    /// The policy account behind the wallet: no key path, and a single `multi` leaf over the
+    /// keys of the co-owners.
+    pub fn policy_account(&self) -> PolicyAccount {
+        PolicyAccount {
+            owner: self.creator,
+            key_path_owner: None,
+            policy: Policy::Multi {
+                threshold: self.threshold,
+                keys: self.co_owners.clone(),
+            },
+        }
+    }
+}
+
+/// Records the caller's approval of a proposal and executes it once enough co-owners approved.
+///
+/// Approving an already approved proposal is allowed; it retries the execution if a previous
+/// attempt failed.
+pub async fn approve(
+    ctx: &BitcoinContext,
+    proposal_id: u64,
+    approver: Principal,
+) -> Result<SpendProposal, String> {
+    let (wallet, proposal) = mutate_state(|state| {
+        let proposal = state
+            .spend_proposals
+            .get_mut(&proposal_id)
+            .ok_or_else(|| format!("No spend proposal with id {}", proposal_id))?;
+        let wallet = state.shared_wallets[&proposal.wallet_id].clone();
+
+        if !wallet.co_owners.contains(&approver) {
+            return Err("Only co-owners can approve a spend".to_string());
+        }
+        if proposal.status != ProposalStatus::Pending {
+            return Err("Spend proposal is no longer pending".to_string());
+        }
+        if !proposal.approvals.contains(&approver) {
+            proposal.approvals.push(approver);
+        }
+
+        // Claim the execution while still holding the state, so that concurrent approvals
+        // cannot broadcast the same proposal twice.
+        if proposal.approvals.len() >= wallet.threshold as usize {
+            proposal.status = ProposalStatus::Executing;
+        }
+        Ok((wallet, proposal.clone()))
+    })?;
+
+    if proposal.status != ProposalStatus::Executing {
+        return Ok(proposal);
+    }
+
+    // Should the execution fail, or trap after an await, the proposal is pending again.
+    let _guard = ExecutionGuard { proposal_id };
+    let txid = execute(ctx, &wallet, &proposal).await?;
+    set_status(proposal_id, ProposalStatus::Executed(txid));
+
+    Ok(read_state(|state| {
+        state.spend_proposals[&proposal_id].clone()
+    }))
+}
+
+// Resets a proposal claimed for execution to pending when dropped, unless it has been executed
+// in the meantime. When a call traps after an await, its future is dropped in a cleanup
+// callback, so this runs then as well.
+struct ExecutionGuard {
+    proposal_id: u64,
+}
+
+impl Drop for ExecutionGuard {
+    fn drop(&mut self) {
+        mutate_state(|state| {
+            if let Some(proposal) = state.spend_proposals.get_mut(&self.proposal_id) {
+                if proposal.status == ProposalStatus::Executing {
+                    proposal.status = ProposalStatus::Pending;
+                }
+            }
+        });
+    }
+}
+
+fn set_status(proposal_id: u64, status: ProposalStatus) {
+    mutate_state(|state| {
+        if let Some(proposal) = state.spend_proposals.get_mut(&proposal_id) {
+            proposal.status = status;
+        }
+    });
+}
+
+/// Signs the proposed payment with the keys of the approving co-owners and broadcasts it.
+async fn execute(
+    ctx: &BitcoinContext,
+    wallet: &SharedWallet,
+    proposal: &SpendProposal,
+) -> Result<String, String> {
+    let dst_address = Address::from_str(&proposal.destination_address)
+        .map_err(|e| format!("Invalid destination address: {}", e))?
+        .require_network(ctx.bitcoin_network)
+        .map_err(|e| format!("Address not valid for network: {}", e))?;
+
+    let compiled = wallet.policy_account().compile(ctx).await?;
+    let own_address = &compiled.address;
+    let script_spend = compiled.script_spend(0, &proposal.approvals, &[])?;
+
+    // Get all UTXOs for the wallet's address.
+    // Note that pagination may have to be used to get all UTXOs for the given address.
+    // For the sake of simplicity, it is assumed here that the `utxo` field in the response
+    // contains all UTXOs.
+    let own_utxos = bitcoin_get_utxos(&GetUtxosRequest {
+        address: own_address.to_string(),
+        network: ctx.network,
+        filter: None,
+    })
+    .await
+    .map_err(|e| format!("Failed to get UTXOs: {:?}", e))?
+    .utxos;
+
+    if own_utxos.is_empty() {
+        return Err("No UTXOs available for spending".to_string());
+    }
+
+    let fee_per_byte = get_fee_per_byte(ctx).await;
+    let (transaction, prevouts) = p2tr::build_transaction_script_spend(
+        ctx,
+        own_address,
+        &own_utxos,
+        &CoinSelectionStrategy::default(),
+        &[PrimaryOutput::Address(
+            dst_address,
+            proposal.amount_in_satoshi,
+        )],
+        fee_per_byte,
+        &script_spend,
+    )
+    .await?;
+
+    // One signature per approving co-owner, up to the threshold.
+    let signed_transaction = p2tr::sign_transaction_script_spend(
+        ctx,
+        own_address,
+        transaction,
+        prevouts.as_slice(),
+        &script_spend,
+        sign_with_schnorr,
+    )
+    .await;
+
+    // Send the transaction to the Bitcoin network.
+    bitcoin_send_transaction(&SendTransactionRequest {
+        network: ctx.network,
+        transaction: serialize(&signed_transaction),
+    })
+    .await
+    .map_err(|e| format!("Failed to send transaction: {:?}", e))?;
+
+    Ok(signed_transaction.compute_txid().to_string())
+}
@@ -0,0 +1,34 @@
+// This module implements data notarization: timestamping up to 80 bytes, typically a
+// document hash, by embedding them in an OP_RETURN output of a transaction. The smart
+// contract remembers which transactions notarized which data, so that the proof can be
+// looked up later.
+
+use bitcoin::{script::PushBytesBuf, ScriptBuf};
+use candid::{CandidType, Deserialize, Principal};
+
+/// Upper bound on the data embedded in an OP_RETURN output, as relayed by default by
+/// Bitcoin Core.
+pub const MAX_DATA_LENGTH: usize = 80;
+
+/// A transaction that embedded notarized data.
+#[derive(CandidType, Deserialize, Clone)]
+pub struct Notarization {
+    pub txid: String,
+    /// The principal that paid for the transaction.
+    pub notary: Principal,
+    /// Time the transaction was broadcast, in nanoseconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+/// The OP_RETURN script embedding `data`, which must be between 1 and [`MAX_DATA_LENGTH`]
+/// bytes long.
+pub fn op_return_script(data: &[u8]) -> Result<ScriptBuf, String> {
+    if data.is_empty() || data.len() > MAX_DATA_LENGTH {
+        return Err(format!(
+            "Data must be between 1 and {} bytes",
+            MAX_DATA_LENGTH
+        ));
+    }
+    let push_bytes = PushBytesBuf::try_from(data.to_vec()).expect("validated data length");
+    Ok(ScriptBuf::new_op_return(push_bytes))
+}
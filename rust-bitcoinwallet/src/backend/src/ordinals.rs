@@ -0,0 +1,417 @@
+// This module implements the creation of Ordinals inscriptions.
+//
+// An inscription is created with two transactions. The commit transaction pays to a P2TR
+// address whose only leaf checks a signature of the inscriber and then carries the inscription
+// envelope in a branch that is never executed. The reveal transaction spends that output
+// through the leaf, which puts the envelope into the witness and inscribes the first satoshi of
+// the reveal transaction's first non-OP_RETURN output.
+//
+// Rune etchings use the same commit/reveal scheme to commit to the name of the rune.
+
+use bitcoin::{
+    absolute::LockTime,
+    hashes::Hash,
+    key::Secp256k1,
+    opcodes::{
+        all::{OP_CHECKSIG, OP_ENDIF, OP_IF},
+        OP_FALSE,
+    },
+    script::{Builder, PushBytes},
+    taproot::{LeafVersion, TaprootBuilder},
+    Address, Amount, Sequence, Transaction, TxOut, Txid, XOnlyPublicKey,
+};
+use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::bitcoin_canister::{MillisatoshiPerByte, Outpoint, Utxo};
+
+use crate::{
+    account::{derivation_path, DEFAULT_ACCOUNT},
+    btc::{build_sweep_with_fee, PrimaryOutput},
+    p2tr::{self, ScriptSpend, ScriptWitnessItem},
+    schnorr::{get_schnorr_public_key, sign_with_schnorr},
+    BitcoinContext,
+};
+
+/// The signed reveal transaction of an inscription, kept from before its commit transaction
+/// is broadcast until the reveal transaction is accepted, so that it can be broadcast again
+/// if that fails, see `retry_inscription_reveal`.
+#[derive(CandidType, Deserialize, Clone)]
+pub struct PendingReveal {
+    pub owner: Principal,
+    /// The reveal transaction, consensus-encoded.
+    pub reveal_transaction: Vec<u8>,
+}
+
+/// Upper bound on the size of an inscription body, which keeps the reveal transaction well
+/// below the standard transaction weight limit.
+pub const MAX_BODY_LENGTH: usize = 350_000;
+
+/// Upper bound on the length of a MIME content type.
+pub const MAX_CONTENT_TYPE_LENGTH: usize = 255;
+
+/// Largest data push allowed in tapscript.
+const MAX_PUSH_LENGTH: usize = 520;
+
+/// The content of an inscription.
+pub struct Envelope {
+    /// MIME type of the body, e.g. `text/plain;charset=utf-8`.
+    pub content_type: String,
+    pub body: Vec<u8>,
+}
+
+impl Envelope {
+    /// Checks that the content type and body are within the supported limits.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.content_type.is_empty() || self.content_type.len() > MAX_CONTENT_TYPE_LENGTH {
+            return Err(format!(
+                "Content type must be between 1 and {} bytes",
+                MAX_CONTENT_TYPE_LENGTH
+            ));
+        }
+        if self.body.len() > MAX_BODY_LENGTH {
+            return Err(format!("Body must be at most {} bytes", MAX_BODY_LENGTH));
+        }
+        Ok(())
+    }
+
+    /// The data pushes of the envelope: `"ord" 1 <content type> 0 <body>`, with the body split
+    /// into pushes of at most 520 bytes.
+    pub fn pushes(&self) -> Vec<Vec<u8>> {
+        let mut pushes = vec![
+            b"ord".to_vec(),
+            vec![1],
+            self.content_type.as_bytes().to_vec(),
+            vec![],
+        ];
+        pushes.extend(self.body.chunks(MAX_PUSH_LENGTH).map(<[u8]>::to_vec));
+        pushes
+    }
+}
+
+/// The output of a commit transaction, and how to spend it in the reveal transaction.
+pub struct Commit {
+    pub address: Address,
+    pub script_spend: ScriptSpend,
+    /// The derivation path of the inscriber's key, the internal key of `address`.
+    pub derivation_path: Vec<Vec<u8>>,
+    /// The Merkle root of the script tree, which tweaks the internal key.
+    pub merkle_root_hash: Vec<u8>,
+}
+
+/// Derives the commit address carrying `pushes` for `inscriber`, together with the script path
+/// spend that reveals them.
+///
+/// The only leaf is `<key> OP_CHECKSIG OP_FALSE OP_IF <pushes> OP_ENDIF`. The inscriber's key
+/// is used both as the internal key and in the leaf, so the commit output can also be recovered
+/// through the key path.
+pub async fn commit_address(
+    ctx: &BitcoinContext,
+    inscriber: Principal,
+    pushes: &[Vec<u8>],
+) -> Result<Commit, String> {
+    let derivation_path = derivation_path(inscriber, DEFAULT_ACCOUNT);
+    let public_key = get_schnorr_public_key(ctx, derivation_path.clone()).await;
+    let key = XOnlyPublicKey::from(bitcoin::PublicKey::from_slice(&public_key).unwrap());
+    Commit::new(key, derivation_path, pushes, ctx.bitcoin_network)
+}
+
+impl Commit {
+    /// The commit output carrying `pushes` for the inscriber's `key`, which is derived at
+    /// `derivation_path`, see [`commit_address`].
+    pub fn new(
+        key: XOnlyPublicKey,
+        derivation_path: Vec<Vec<u8>>,
+        pushes: &[Vec<u8>],
+        network: bitcoin::Network,
+    ) -> Result<Commit, String> {
+        let mut builder = Builder::new()
+            .push_x_only_key(&key)
+            .push_opcode(OP_CHECKSIG)
+            .push_opcode(OP_FALSE)
+            .push_opcode(OP_IF);
+        for push in pushes {
+            builder = builder.push_slice(push_bytes(push));
+        }
+        let leaf_script = builder.push_opcode(OP_ENDIF).into_script();
+        let secp256k1_engine = Secp256k1::new();
+        let spend_info = TaprootBuilder::new()
+            .add_leaf(0, leaf_script.clone())
+            .expect("a single leaf at depth 0 is a valid tree")
+            .finalize(&secp256k1_engine, key)
+            .map_err(|_| "Failed to finalize script tree".to_string())?;
+        let address = Address::p2tr_tweaked(spend_info.output_key(), network);
+
+        let control_block = spend_info
+            .control_block(&(leaf_script.clone(), LeafVersion::TapScript))
+            .expect("leaf is part of the tree");
+        let merkle_root_hash = spend_info
+            .merkle_root()
+            .expect("the tree has a leaf")
+            .to_byte_array()
+            .to_vec();
+
+        Ok(Commit {
+            address,
+            script_spend: ScriptSpend {
+                leaf_script,
+                control_block,
+                witness: vec![ScriptWitnessItem::Signature(derivation_path.clone())],
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                lock_time: LockTime::ZERO,
+            },
+            derivation_path,
+            merkle_root_hash,
+        })
+    }
+}
+
+/// Computes the fee of the reveal transaction, before the commit transaction exists.
+///
+/// Amounts are encoded with a fixed width, so neither the txid nor the value of the commit
+/// output affect the size of the reveal transaction.
+pub async fn reveal_fee(
+    ctx: &BitcoinContext,
+    commit: &Commit,
+    fixed_outputs: &[PrimaryOutput],
+    destination: &Address,
+    fee_per_byte: MillisatoshiPerByte,
+) -> Result<u64, String> {
+    let value = Amount::MAX_MONEY.to_sat();
+    let (transaction, _) = build_reveal_transaction(
+        ctx,
+        commit,
+        Txid::all_zeros(),
+        value,
+        fixed_outputs,
+        destination,
+        fee_per_byte,
+    )
+    .await?;
+    let outputs_value: u64 = transaction
+        .output
+        .iter()
+        .map(|output| output.value.to_sat())
+        .sum();
+    Ok(value - outputs_value)
+}
+
+/// Builds the reveal transaction, which pays `fixed_outputs` from the commit output
+/// `commit_txid:0` holding `value` satoshi and sends the rest minus the fee to `destination`.
+pub async fn build_reveal_transaction(
+    ctx: &BitcoinContext,
+    commit: &Commit,
+    commit_txid: Txid,
+    value: u64,
+    fixed_outputs: &[PrimaryOutput],
+    destination: &Address,
+    fee_per_byte: MillisatoshiPerByte,
+) -> Result<(Transaction, Vec<TxOut>), String> {
+    p2tr::build_sweep_transaction(
+        ctx,
+        &commit.address,
+        &[commit_utxo(commit_txid, value)],
+        fixed_outputs,
+        destination,
+        fee_per_byte,
+        Some(&commit.script_spend),
+    )
+    .await
+}
+
+/// Builds the reveal transaction like [`build_reveal_transaction`], but paying exactly `fee`,
+/// the reveal fee the commit output was funded with.
+pub fn build_reveal_transaction_with_fee(
+    commit: &Commit,
+    commit_txid: Txid,
+    value: u64,
+    fixed_outputs: &[PrimaryOutput],
+    destination: &Address,
+    fee: u64,
+) -> Result<(Transaction, Vec<TxOut>), String> {
+    build_sweep_with_fee(
+        &[commit_utxo(commit_txid, value)],
+        &commit.address,
+        fixed_outputs,
+        destination,
+        fee,
+    )
+}
+
+/// Builds a transaction that sends the commit output `commit_txid:0` holding `value` satoshi
+/// back to `destination` through the key path, for when the reveal transaction cannot be
+/// built anymore.
+pub async fn build_recovery_transaction(
+    ctx: &BitcoinContext,
+    commit: &Commit,
+    commit_txid: Txid,
+    value: u64,
+    destination: &Address,
+    fee_per_byte: MillisatoshiPerByte,
+) -> Result<(Transaction, Vec<TxOut>), String> {
+    p2tr::build_sweep_transaction(
+        ctx,
+        &commit.address,
+        &[commit_utxo(commit_txid, value)],
+        &[],
+        destination,
+        fee_per_byte,
+        None,
+    )
+    .await
+}
+
+/// Signs the recovery transaction with the inscriber's key, tweaked with the Merkle root of
+/// the commit leaf.
+pub async fn sign_recovery_transaction(
+    ctx: &BitcoinContext,
+    commit: &Commit,
+    transaction: Transaction,
+    prevouts: &[TxOut],
+) -> Transaction {
+    p2tr::sign_transaction_key_spend(
+        ctx,
+        transaction,
+        prevouts,
+        std::slice::from_ref(&commit.derivation_path),
+        commit.merkle_root_hash.clone(),
+        sign_with_schnorr,
+    )
+    .await
+}
+
+// The commit output is the primary output of the commit transaction, which comes first.
+fn commit_utxo(commit_txid: Txid, value: u64) -> Utxo {
+    Utxo {
+        outpoint: Outpoint {
+            txid: commit_txid.to_byte_array().to_vec(),
+            vout: 0,
+        },
+        value,
+        height: 0,
+    }
+}
+
+/// Signs the reveal transaction with the inscriber's key in the commit leaf.
+pub async fn sign_reveal_transaction(
+    ctx: &BitcoinContext,
+    commit: &Commit,
+    transaction: Transaction,
+    prevouts: &[TxOut],
+) -> Transaction {
+    p2tr::sign_transaction_script_spend(
+        ctx,
+        &commit.address,
+        transaction,
+        prevouts,
+        &commit.script_spend,
+        sign_with_schnorr,
+    )
+    .await
+}
+
+fn push_bytes(data: &[u8]) -> &PushBytes {
+    <&PushBytes>::try_from(data).expect("pushes are at most 520 bytes")
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{key::Keypair, Amount};
+    use ic_cdk::bitcoin_canister::{Outpoint, Utxo};
+
+    use super::*;
+    use crate::{
+        btc::{build_sweep_with_predicted_fee, dust_limit},
+        runes::{Etching, Runestone},
+    };
+
+    // Builds the reveal transaction of a rune etching whose commit output covers exactly the
+    // postage of `destination` and the reveal fee.
+    fn reveal_with_postage(destination: &Address) -> Transaction {
+        let secp256k1_engine = Secp256k1::new();
+        let keypair = Keypair::from_seckey_slice(&secp256k1_engine, &[1; 32]).unwrap();
+        let (key, _) = keypair.x_only_public_key();
+        let etching = Etching {
+            rune: "UNCOMMON•GOODS".to_string(),
+            divisibility: None,
+            symbol: Some("⧉".to_string()),
+            premine: Some(1_000),
+            terms: None,
+        };
+        let commit = Commit::new(
+            key,
+            vec![vec![0]],
+            &[etching.commitment().unwrap()],
+            bitcoin::Network::Regtest,
+        )
+        .unwrap();
+        let runestone = Runestone {
+            etching: Some(etching),
+            ..Default::default()
+        }
+        .encipher()
+        .unwrap();
+        let fixed_outputs = [PrimaryOutput::OpReturn(runestone)];
+        // The commit output covers the postage and the reveal fee, see `reveal_fee`.
+        let value = Amount::MAX_MONEY.to_sat();
+        let utxo = Utxo {
+            outpoint: Outpoint {
+                txid: vec![0; 32],
+                vout: 0,
+            },
+            value,
+            height: 0,
+        };
+        let (transaction, _, _) = build_sweep_with_predicted_fee(
+            &[utxo],
+            &commit.address,
+            commit.script_spend.input_prediction(),
+            &fixed_outputs,
+            destination,
+            10_000,
+        )
+        .unwrap();
+        let reveal_fee = value - transaction.output[1].value.to_sat();
+
+        let (transaction, _) = build_reveal_transaction_with_fee(
+            &commit,
+            Txid::all_zeros(),
+            dust_limit(destination) + reveal_fee,
+            &fixed_outputs,
+            destination,
+            reveal_fee,
+        )
+        .unwrap();
+        transaction
+    }
+
+    #[test]
+    fn builds_reveal_for_p2tr_postage() {
+        let secp256k1_engine = Secp256k1::new();
+        let keypair = Keypair::from_seckey_slice(&secp256k1_engine, &[2; 32]).unwrap();
+        let (key, _) = keypair.x_only_public_key();
+        let destination = Address::p2tr(&secp256k1_engine, key, None, bitcoin::Network::Regtest);
+
+        let transaction = reveal_with_postage(&destination);
+        assert_eq!(transaction.output.len(), 2);
+        assert_eq!(transaction.output[1].value.to_sat(), 330);
+        assert_eq!(
+            transaction.output[1].script_pubkey,
+            destination.script_pubkey()
+        );
+    }
+
+    #[test]
+    fn builds_reveal_for_p2pkh_postage() {
+        let secp256k1_engine = Secp256k1::new();
+        let keypair = Keypair::from_seckey_slice(&secp256k1_engine, &[2; 32]).unwrap();
+        let key = bitcoin::PublicKey::new(keypair.public_key());
+        let destination = Address::p2pkh(key, bitcoin::Network::Regtest);
+
+        let transaction = reveal_with_postage(&destination);
+        assert_eq!(transaction.output.len(), 2);
+        assert_eq!(transaction.output[1].value.to_sat(), 546);
+        assert_eq!(
+            transaction.output[1].script_pubkey,
+            destination.script_pubkey()
+        );
+    }
+}
@@ -1,95 +1,225 @@
 use crate::{
-    btc::{build_transaction_with_fee, select_one_utxo, select_utxos_greedy, PrimaryOutput},
+    btc::{build_selected_transaction, build_sweep_with_predicted_fee, PrimaryOutput},
+    coin_selection::{CoinSelection, SelectionParams},
     schnorr::mock_sign_with_schnorr,
+    vsize::{check_prediction, P2TR_KEY_PATH},
     BitcoinContext,
 };
 use bitcoin::{
+    absolute::LockTime,
     blockdata::witness::Witness,
     hashes::Hash,
     secp256k1::schnorr::Signature,
     sighash::{SighashCache, TapSighashType},
+    taproot::{ControlBlock, LeafVersion, TapLeafHash},
+    transaction::InputWeightPrediction,
     Address, AddressType, ScriptBuf, Sequence, Transaction, TxOut,
 };
 use ic_cdk::bitcoin_canister::{MillisatoshiPerByte, Utxo};
 
-pub enum SelectUtxosMode {
-    Greedy,
-    #[allow(dead_code)]
-    Single,
+/// A single element of a script path witness, listed bottom to top.
+pub enum ScriptWitnessItem {
+    /// A Schnorr signature by the (untweaked) key derived at this derivation path.
+    Signature(Vec<Vec<u8>>),
+    /// A raw stack element, e.g. a hash preimage or the empty vector standing in
+    /// for a signature that is not provided.
+    Data(Vec<u8>),
 }
 
-// Builds a P2TR transaction to send the given `amount` of satoshis to the
-// destination address.
+/// Everything needed to spend P2TR outputs through one leaf of their script tree.
+pub struct ScriptSpend {
+    pub leaf_script: ScriptBuf,
+    pub control_block: ControlBlock,
+    /// Witness elements satisfying `leaf_script`, excluding the script and control block.
+    pub witness: Vec<ScriptWitnessItem>,
+    /// Sequence of every input, which must satisfy any `OP_CHECKSEQUENCEVERIFY` in the leaf.
+    pub sequence: Sequence,
+    /// Lock time of the transaction, which must satisfy any `OP_CHECKLOCKTIMEVERIFY` in the leaf.
+    pub lock_time: LockTime,
+}
+
+impl ScriptSpend {
+    /// The size prediction of an input spent through this leaf, see
+    /// `sign_transaction_script_spend` for the witness it gets.
+    pub fn input_prediction(&self) -> InputWeightPrediction {
+        let witness = self.witness.iter().map(|item| match item {
+            // A Schnorr signature with the default sighash type.
+            ScriptWitnessItem::Signature(_) => 64,
+            ScriptWitnessItem::Data(data) => data.len(),
+        });
+        InputWeightPrediction::new(
+            0,
+            witness.chain([self.leaf_script.len(), self.control_block.size()]),
+        )
+    }
+}
+
+// Builds a P2TR transaction paying `primary_outputs` from the UTXOs that
+// `coin_selection` picks among `own_utxos`, with change to `own_address`.
 pub(crate) async fn build_transaction(
     ctx: &BitcoinContext,
     own_address: &Address,
     own_utxos: &[Utxo],
-    utxos_mode: SelectUtxosMode,
-    primary_output: &PrimaryOutput,
+    coin_selection: &dyn CoinSelection,
+    primary_outputs: &[PrimaryOutput],
+    fee_per_byte: MillisatoshiPerByte,
+) -> Result<(Transaction, Vec<TxOut>), String> {
+    build_transaction_for_path(
+        ctx,
+        own_address,
+        own_utxos,
+        coin_selection,
+        primary_outputs,
+        fee_per_byte,
+        None,
+    )
+    .await
+}
+
+// Builds a P2TR transaction like `build_transaction`, but sizes the fee for
+// inputs that are spent through the script path described by `script_spend`.
+pub(crate) async fn build_transaction_script_spend(
+    ctx: &BitcoinContext,
+    own_address: &Address,
+    own_utxos: &[Utxo],
+    coin_selection: &dyn CoinSelection,
+    primary_outputs: &[PrimaryOutput],
+    fee_per_byte: MillisatoshiPerByte,
+    script_spend: &ScriptSpend,
+) -> Result<(Transaction, Vec<TxOut>), String> {
+    build_transaction_for_path(
+        ctx,
+        own_address,
+        own_utxos,
+        coin_selection,
+        primary_outputs,
+        fee_per_byte,
+        Some(script_spend),
+    )
+    .await
+}
+
+async fn build_transaction_for_path(
+    ctx: &BitcoinContext,
+    own_address: &Address,
+    own_utxos: &[Utxo],
+    coin_selection: &dyn CoinSelection,
+    primary_outputs: &[PrimaryOutput],
     fee_per_byte: MillisatoshiPerByte,
+    script_spend: Option<&ScriptSpend>,
 ) -> Result<(Transaction, Vec<TxOut>), String> {
-    // We have a chicken-and-egg problem where we need to know the length
-    // of the transaction in order to compute its proper fee, but we need
-    // to know the proper fee in order to figure out the inputs needed for
-    // the transaction.
-    //
-    // We solve this problem iteratively. We start with a fee of zero, build
-    // and sign a transaction, see what its size is, and then update the fee,
-    // rebuild the transaction, until the fee is set to the correct amount.
-    let amount = match primary_output {
-        PrimaryOutput::Address(_, amount) => *amount,
-        PrimaryOutput::OpReturn(_) => 0,
+    // Predict the size a signed input adds, so that UTXOs can be weighed by
+    // their effective value, select the UTXOs to spend, and build the
+    // transaction with the fee for its predicted size.
+    let input = match script_spend {
+        None => P2TR_KEY_PATH,
+        Some(script_spend) => script_spend.input_prediction(),
     };
-    let mut total_fee = 0;
-    loop {
-        let utxos_to_spend = match utxos_mode {
-            SelectUtxosMode::Greedy => select_utxos_greedy(own_utxos, amount, total_fee),
-            SelectUtxosMode::Single => select_one_utxo(own_utxos, amount, total_fee),
-        }?;
-
-        let (transaction, prevouts) =
-            build_transaction_with_fee(utxos_to_spend, own_address, primary_output, total_fee)?;
-
-        // Sign the transaction. In this case, we only care about the size
-        // of the signed transaction, so we use a mock signer here for
-        // efficiency.
-        //
-        // Note: it doesn't matter which particular spending path to use, key or
-        // script path, since the difference is only how the signature is
-        // computed, which is a dummy signing function in our case.
-        let signed_transaction = sign_transaction_key_spend(
-            ctx,
-            own_address,
-            transaction.clone(),
-            &prevouts,
-            vec![], // mock derivation path
-            vec![],
-            mock_sign_with_schnorr,
-        )
-        .await;
+    let params = SelectionParams::new(primary_outputs, own_address, input, fee_per_byte);
+    let selection = coin_selection.select(own_utxos, &params)?;
+    let (transaction, prevouts, vsize) =
+        build_selected_transaction(&selection, &params, own_address, primary_outputs)?;
+
+    if cfg!(debug_assertions) {
+        let signed_transaction =
+            mock_sign_transaction(ctx, own_address, &transaction, &prevouts, script_spend).await;
+        check_prediction(vsize, &signed_transaction);
+    }
+    Ok((transaction, prevouts))
+}
+
+// Builds a P2TR transaction that spends all of `own_utxos`, pays
+// `fixed_outputs` and sends the remaining value minus the fee to
+// `destination`, which comes last, without a change output.
+//
+// The fee is sized for the key path, or for the script path described by
+// `script_spend` if one is given.
+pub(crate) async fn build_sweep_transaction(
+    ctx: &BitcoinContext,
+    own_address: &Address,
+    own_utxos: &[Utxo],
+    fixed_outputs: &[PrimaryOutput],
+    destination: &Address,
+    fee_per_byte: MillisatoshiPerByte,
+    script_spend: Option<&ScriptSpend>,
+) -> Result<(Transaction, Vec<TxOut>), String> {
+    let input = match script_spend {
+        None => P2TR_KEY_PATH,
+        Some(script_spend) => script_spend.input_prediction(),
+    };
+    let (transaction, prevouts, vsize) = build_sweep_with_predicted_fee(
+        own_utxos,
+        own_address,
+        input,
+        fixed_outputs,
+        destination,
+        fee_per_byte,
+    )?;
+
+    if cfg!(debug_assertions) {
+        let signed_transaction =
+            mock_sign_transaction(ctx, own_address, &transaction, &prevouts, script_spend).await;
+        check_prediction(vsize, &signed_transaction);
+    }
+    Ok((transaction, prevouts))
+}
 
-        let tx_vsize = signed_transaction.vsize() as u64;
-        if (tx_vsize * fee_per_byte) / 1000 == total_fee {
-            return Ok((transaction, prevouts));
-        } else {
-            total_fee = (tx_vsize * fee_per_byte) / 1000;
+// Signs a copy of `transaction` with a mock signer, to cross-check the size
+// prediction in debug builds.
+//
+// Note: the spending path matters for the size, since a script path witness
+// also carries the leaf script, the control block and whatever else the leaf
+// needs to be satisfied.
+async fn mock_sign_transaction(
+    ctx: &BitcoinContext,
+    own_address: &Address,
+    transaction: &Transaction,
+    prevouts: &[TxOut],
+    script_spend: Option<&ScriptSpend>,
+) -> Transaction {
+    match script_spend {
+        None => {
+            sign_transaction_key_spend(
+                ctx,
+                transaction.clone(),
+                prevouts,
+                &vec![vec![]; prevouts.len()], // mock derivation paths
+                vec![],
+                mock_sign_with_schnorr,
+            )
+            .await
+        }
+        Some(script_spend) => {
+            sign_transaction_script_spend(
+                ctx,
+                own_address,
+                transaction.clone(),
+                prevouts,
+                script_spend,
+                mock_sign_with_schnorr,
+            )
+            .await
         }
     }
 }
 
 // Sign a P2TR key spend transaction.
 //
+// Input `i` is signed with the key at `derivation_paths[i]`, so a single
+// transaction can spend outputs of several addresses derived for the same
+// principal.
+//
 // IMPORTANT: This method is for demonstration purposes only and it only
 // supports signing transactions if:
 //
-// 1. All the inputs are referencing outpoints that are owned by `own_address`.
-// 2. `own_address` is a P2TR address.
+// 1. All the inputs are referencing P2TR outputs.
+// 2. The output spent by input `i` belongs to the key at `derivation_paths[i]`
+//    tweaked with `merkle_root_hash`.
 pub async fn sign_transaction_key_spend<SignFun, Fut>(
     ctx: &BitcoinContext,
-    own_address: &Address,
     mut transaction: Transaction,
     prevouts: &[TxOut],
-    derivation_path: Vec<Vec<u8>>,
+    derivation_paths: &[Vec<Vec<u8>>],
     merkle_root_hash: Vec<u8>,
     signer: SignFun,
 ) -> Transaction
@@ -97,7 +227,10 @@ where
     SignFun: Fn(String, Vec<Vec<u8>>, Option<Vec<u8>>, Vec<u8>) -> Fut,
     Fut: std::future::Future<Output = Vec<u8>>,
 {
-    assert_eq!(own_address.address_type(), Some(AddressType::P2tr),);
+    assert_eq!(derivation_paths.len(), transaction.input.len());
+    assert!(prevouts
+        .iter()
+        .all(|prevout| prevout.script_pubkey.is_p2tr()));
 
     for input in transaction.input.iter_mut() {
         input.script_sig = ScriptBuf::default();
@@ -105,9 +238,7 @@ where
         input.sequence = Sequence::ENABLE_RBF_NO_LOCKTIME;
     }
 
-    let num_inputs = transaction.input.len();
-
-    for i in 0..num_inputs {
+    for (i, derivation_path) in derivation_paths.iter().enumerate() {
         let mut sighasher = SighashCache::new(&mut transaction);
 
         let signing_data = sighasher
@@ -139,3 +270,85 @@ where
 
     transaction
 }
+
+// Sign a P2TR script path spend transaction.
+//
+// Every input is spent through the leaf described by `script_spend`. Each
+// `ScriptWitnessItem::Signature` is replaced by a signature of the untweaked
+// key at the given derivation path, since script path signatures are checked
+// against the keys in the leaf script, not against the output key.
+//
+// IMPORTANT: This method is for demonstration purposes only and it only
+// supports signing transactions if:
+//
+// 1. All the inputs are referencing outpoints that are owned by `own_address`.
+// 2. `own_address` is a P2TR address committing to `script_spend.leaf_script`.
+pub async fn sign_transaction_script_spend<SignFun, Fut>(
+    ctx: &BitcoinContext,
+    own_address: &Address,
+    mut transaction: Transaction,
+    prevouts: &[TxOut],
+    script_spend: &ScriptSpend,
+    signer: SignFun,
+) -> Transaction
+where
+    SignFun: Fn(String, Vec<Vec<u8>>, Option<Vec<u8>>, Vec<u8>) -> Fut,
+    Fut: std::future::Future<Output = Vec<u8>>,
+{
+    assert_eq!(own_address.address_type(), Some(AddressType::P2tr),);
+
+    transaction.lock_time = script_spend.lock_time;
+    for input in transaction.input.iter_mut() {
+        input.script_sig = ScriptBuf::default();
+        input.witness = Witness::default();
+        input.sequence = script_spend.sequence;
+    }
+
+    let leaf_hash = TapLeafHash::from_script(&script_spend.leaf_script, LeafVersion::TapScript);
+    let num_inputs = transaction.input.len();
+
+    for i in 0..num_inputs {
+        let mut sighasher = SighashCache::new(&mut transaction);
+
+        let signing_data = sighasher
+            .taproot_script_spend_signature_hash(
+                i,
+                &bitcoin::sighash::Prevouts::All(prevouts),
+                leaf_hash,
+                TapSighashType::Default,
+            )
+            .expect("Failed to encode signing data")
+            .as_byte_array()
+            .to_vec();
+
+        let mut witness = Witness::new();
+        for item in script_spend.witness.iter() {
+            match item {
+                ScriptWitnessItem::Signature(derivation_path) => {
+                    // No Merkle root: the leaf keys are used untweaked.
+                    let raw_signature = signer(
+                        ctx.key_name.to_string(),
+                        derivation_path.clone(),
+                        None,
+                        signing_data.clone(),
+                    )
+                    .await;
+                    let signature = bitcoin::taproot::Signature {
+                        signature: Signature::from_slice(&raw_signature)
+                            .expect("failed to parse signature"),
+                        sighash_type: TapSighashType::Default,
+                    };
+                    witness.push(signature.to_vec());
+                }
+                ScriptWitnessItem::Data(data) => witness.push(data),
+            }
+        }
+        witness.push(script_spend.leaf_script.as_bytes());
+        witness.push(script_spend.control_block.serialize());
+
+        // Update the witness stack.
+        *sighasher.witness_mut(i).unwrap() = witness;
+    }
+
+    transaction
+}
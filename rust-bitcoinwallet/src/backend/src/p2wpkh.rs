@@ -0,0 +1,160 @@
+use crate::{
+    btc::{build_selected_transaction, build_sweep_with_predicted_fee, PrimaryOutput},
+    coin_selection::{CoinSelection, SelectionParams},
+    ecdsa::mock_sign_with_ecdsa,
+    vsize::{check_prediction, P2WPKH},
+    BitcoinContext,
+};
+use bitcoin::{
+    hashes::Hash,
+    secp256k1::ecdsa::Signature,
+    sighash::{EcdsaSighashType, SighashCache},
+    Address, CompressedPublicKey, ScriptBuf, Sequence, Transaction, TxOut, Witness,
+};
+use ic_cdk::bitcoin_canister::{MillisatoshiPerByte, Utxo};
+
+// Builds a P2WPKH transaction paying `primary_outputs` from the UTXOs that
+// `coin_selection` picks among `own_utxos`, with change to `own_address`.
+pub(crate) async fn build_transaction(
+    ctx: &BitcoinContext,
+    own_public_key: &CompressedPublicKey,
+    own_address: &Address,
+    own_utxos: &[Utxo],
+    coin_selection: &dyn CoinSelection,
+    primary_outputs: &[PrimaryOutput],
+    fee_per_byte: MillisatoshiPerByte,
+) -> Result<(Transaction, Vec<TxOut>), String> {
+    // Predict the size a signed input adds, so that UTXOs can be weighed by
+    // their effective value, select the UTXOs to spend, and build the
+    // transaction with the fee for its predicted size.
+    let params = SelectionParams::new(primary_outputs, own_address, P2WPKH, fee_per_byte);
+    let selection = coin_selection.select(own_utxos, &params)?;
+    let (transaction, prevouts, vsize) =
+        build_selected_transaction(&selection, &params, own_address, primary_outputs)?;
+
+    if cfg!(debug_assertions) {
+        let signed_transaction =
+            mock_sign_transaction(ctx, own_public_key, &transaction, &prevouts).await;
+        check_prediction(vsize, &signed_transaction);
+    }
+    Ok((transaction, prevouts))
+}
+
+// Builds a P2WPKH transaction that spends all of `own_utxos` and sends
+// their total value minus the fee to `destination`, without a change output.
+pub(crate) async fn build_sweep_transaction(
+    ctx: &BitcoinContext,
+    own_public_key: &CompressedPublicKey,
+    own_address: &Address,
+    own_utxos: &[Utxo],
+    destination: &Address,
+    fee_per_byte: MillisatoshiPerByte,
+) -> Result<(Transaction, Vec<TxOut>), String> {
+    let (transaction, prevouts, vsize) = build_sweep_with_predicted_fee(
+        own_utxos,
+        own_address,
+        P2WPKH,
+        &[],
+        destination,
+        fee_per_byte,
+    )?;
+
+    if cfg!(debug_assertions) {
+        let signed_transaction =
+            mock_sign_transaction(ctx, own_public_key, &transaction, &prevouts).await;
+        check_prediction(vsize, &signed_transaction);
+    }
+    Ok((transaction, prevouts))
+}
+
+// Signs a copy of `transaction` with a mock signer, to cross-check the size
+// prediction in debug builds.
+async fn mock_sign_transaction(
+    ctx: &BitcoinContext,
+    own_public_key: &CompressedPublicKey,
+    transaction: &Transaction,
+    prevouts: &[TxOut],
+) -> Transaction {
+    sign_transaction(
+        ctx,
+        transaction.clone(),
+        prevouts,
+        &vec![(*own_public_key, vec![]); prevouts.len()], // mock derivation paths
+        mock_sign_with_ecdsa,
+    )
+    .await
+}
+
+// Sign a P2WPKH transaction.
+//
+// Input `i` is signed with the key at `keys[i]`, given as the public key and
+// the derivation path it was derived at, so a single transaction can spend
+// outputs of several addresses derived for the same principal.
+//
+// IMPORTANT: This method is for demonstration purposes only and it only
+// supports signing transactions if:
+//
+// 1. All the inputs are referencing P2WPKH outputs.
+// 2. The output spent by input `i` belongs to the public key in `keys[i]`.
+pub async fn sign_transaction<SignFun, Fut>(
+    ctx: &BitcoinContext,
+    mut transaction: Transaction,
+    prevouts: &[TxOut],
+    keys: &[(CompressedPublicKey, Vec<Vec<u8>>)],
+    signer: SignFun,
+) -> Transaction
+where
+    SignFun: Fn(String, Vec<Vec<u8>>, Vec<u8>) -> Fut,
+    Fut: std::future::Future<Output = Vec<u8>>,
+{
+    assert_eq!(keys.len(), transaction.input.len());
+    assert!(prevouts
+        .iter()
+        .all(|prevout| prevout.script_pubkey.is_p2wpkh()));
+
+    for input in transaction.input.iter_mut() {
+        input.script_sig = ScriptBuf::default();
+        input.witness = Witness::default();
+        input.sequence = Sequence::ENABLE_RBF_NO_LOCKTIME;
+    }
+
+    // There is exactly one previous output and one key per input.
+    for (i, (prevout, (public_key, derivation_path))) in prevouts.iter().zip(keys).enumerate() {
+        let mut sighasher = SighashCache::new(&mut transaction);
+
+        // BIP-143 commits to the value of the output being spent, which is
+        // why the previous outputs are needed here.
+        let signing_data = sighasher
+            .p2wpkh_signature_hash(
+                i,
+                &prevout.script_pubkey,
+                prevout.value,
+                EcdsaSighashType::All,
+            )
+            .expect("Failed to compute sighash")
+            .as_byte_array()
+            .to_vec();
+
+        let raw_signature = signer(
+            ctx.key_name.to_string(),
+            derivation_path.clone(),
+            signing_data,
+        )
+        .await;
+
+        // The management canister returns compact signatures; Bitcoin expects
+        // DER encoding with a low S value followed by the sighash type.
+        let mut signature =
+            Signature::from_compact(&raw_signature).expect("failed to parse signature");
+        signature.normalize_s();
+        let signature = bitcoin::ecdsa::Signature {
+            signature,
+            sighash_type: EcdsaSighashType::All,
+        };
+
+        // Update the witness stack.
+        *sighasher.witness_mut(i).unwrap() = Witness::p2wpkh(&signature, &public_key.0);
+    }
+
+    transaction
+}
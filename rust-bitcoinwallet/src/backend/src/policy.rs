@@ -0,0 +1,500 @@
+// This module implements spending policies for P2TR accounts.
+//
+// A policy is a small Miniscript-like expression over the threshold keys of principals,
+// timelocks and hashlocks. It is brought into disjunctive normal form, and every resulting
+// conjunction becomes one tapscript leaf of the account's script tree. An account may
+// additionally name a principal whose key is used as the internal key, which makes the
+// account spendable through the key path as well.
+
+use std::collections::BTreeMap;
+
+use bitcoin::{
+    absolute::LockTime,
+    hashes::{sha256, Hash},
+    key::Secp256k1,
+    opcodes::all::{
+        OP_CHECKSIG, OP_CHECKSIGADD, OP_CHECKSIGVERIFY, OP_CLTV, OP_CSV, OP_DROP, OP_EQUAL,
+        OP_EQUALVERIFY, OP_NUMEQUAL, OP_NUMEQUALVERIFY, OP_SHA256, OP_SIZE,
+    },
+    script::Builder,
+    taproot::{LeafVersion, TaprootBuilder, TaprootSpendInfo},
+    Address, PublicKey, ScriptBuf, Sequence, XOnlyPublicKey,
+};
+use candid::{CandidType, Deserialize, Principal};
+
+use crate::{
+    p2tr::{ScriptSpend, ScriptWitnessItem},
+    schnorr::get_schnorr_public_key,
+    BitcoinContext,
+};
+
+/// Upper bound on the number of leaves a policy may compile to.
+const MAX_LEAVES: usize = 32;
+
+/// Upper bound on the number of keys in a single `multi` fragment.
+const MAX_MULTI_KEYS: usize = 20;
+
+/// The x coordinate of the BIP-341 "nothing up my sleeve" point `H`, which has no known
+/// discrete logarithm. Used as the internal key of accounts without a key path.
+const UNSPENDABLE_INTERNAL_KEY: [u8; 32] = [
+    0x50, 0x92, 0x9b, 0x74, 0xc1, 0xa0, 0x49, 0x54, 0xb7, 0x8b, 0x4b, 0x60, 0x35, 0xe9, 0x7a, 0x5e,
+    0x07, 0x8a, 0x5a, 0x0f, 0x28, 0xec, 0x96, 0xd5, 0x47, 0xbf, 0xee, 0x9a, 0xce, 0x80, 0x3a, 0xc0,
+];
+
+/// A spending policy.
+///
+/// Keys are mostly given as principals and resolve to the threshold Schnorr key the smart
+/// contract derives for that principal, so a signature can only be produced on behalf of the
+/// principal itself. A `key` fragment names a key held outside the smart contract instead,
+/// which the smart contract can never sign for.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum Policy {
+    /// A signature of the principal's key.
+    #[serde(rename = "pk")]
+    Pk(Principal),
+    /// A signature of the given 32-byte x-only public key, held outside the smart contract.
+    #[serde(rename = "key")]
+    Key(Vec<u8>),
+    /// Signatures of at least `threshold` of the principals' keys (`OP_CHECKSIGADD`).
+    #[serde(rename = "multi")]
+    Multi {
+        threshold: u32,
+        keys: Vec<Principal>,
+    },
+    /// A relative timelock of the given number of blocks (`OP_CHECKSEQUENCEVERIFY`).
+    #[serde(rename = "older")]
+    Older(u16),
+    /// An absolute timelock at the given block height (`OP_CHECKLOCKTIMEVERIFY`).
+    #[serde(rename = "after")]
+    After(u32),
+    /// Revealing the preimage of the given 32-byte SHA-256 hash.
+    #[serde(rename = "sha256")]
+    Sha256(Vec<u8>),
+    /// All of the sub-policies.
+    #[serde(rename = "and")]
+    And(Vec<Policy>),
+    /// Any of the sub-policies.
+    #[serde(rename = "or")]
+    Or(Vec<Policy>),
+}
+
+/// A P2TR account defined by a spending policy.
+#[derive(CandidType, Deserialize, Clone)]
+pub struct PolicyAccount {
+    /// The principal that created the account.
+    pub owner: Principal,
+    /// The principal whose key is the internal key. Without one, the account can only be
+    /// spent through its script tree.
+    pub key_path_owner: Option<Principal>,
+    pub policy: Policy,
+}
+
+/// An inheritance plan: the heir may spend the owner's inheritance account through a script
+/// path once its outputs have not moved for `inactivity_blocks` blocks.
+#[derive(CandidType, Deserialize, Clone)]
+pub struct InheritancePlan {
+    pub heir: Principal,
+    pub inactivity_blocks: u16,
+}
+
+impl InheritancePlan {
+    /// The policy account behind the inheritance account of `owner`: the owner's key is the
+    /// internal key, and the only leaf is `<inactivity_blocks> OP_CSV OP_DROP <heir> OP_CHECKSIG`.
+    pub fn policy_account(&self, owner: Principal) -> PolicyAccount {
+        PolicyAccount {
+            owner,
+            key_path_owner: Some(owner),
+            policy: Policy::And(vec![
+                Policy::Older(self.inactivity_blocks),
+                Policy::Pk(self.heir),
+            ]),
+        }
+    }
+}
+
+/// A tapscript leaf compiled from one conjunction of the policy.
+pub struct Leaf {
+    pub script: ScriptBuf,
+    fragments: Vec<Policy>,
+}
+
+/// A policy account with all keys resolved and its script tree built.
+pub struct CompiledPolicy {
+    pub address: Address,
+    pub spend_info: TaprootSpendInfo,
+    pub leaves: Vec<Leaf>,
+}
+
+impl PolicyAccount {
+    /// Checks that the policy compiles to an acceptable script tree, without deriving keys.
+    pub fn validate(&self) -> Result<(), String> {
+        for conjunction in conjunctions(&self.policy)? {
+            for fragment in conjunction.iter() {
+                validate_fragment(fragment)?;
+            }
+            if !conjunction.iter().any(|fragment| {
+                matches!(
+                    fragment,
+                    Policy::Pk(_) | Policy::Key(_) | Policy::Multi { .. }
+                )
+            }) {
+                return Err("Every spending path must require at least one signature".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Derives all keys of the account and builds its script tree and address.
+    pub async fn compile(&self, ctx: &BitcoinContext) -> Result<CompiledPolicy, String> {
+        let mut principals = vec![];
+        collect_principals(&self.policy, &mut principals);
+        principals.extend(self.key_path_owner);
+
+        principals.sort();
+        principals.dedup();
+
+        let mut keys = BTreeMap::new();
+        for principal in principals {
+            keys.insert(principal, get_x_only_public_key(ctx, principal).await);
+        }
+
+        let leaves = conjunctions(&self.policy)?
+            .into_iter()
+            .map(|conjunction| Leaf {
+                script: leaf_script(&conjunction, &keys),
+                fragments: conjunction.into_iter().cloned().collect(),
+            })
+            .collect::<Vec<_>>();
+
+        // All leaves are equally likely to be used, so a Huffman tree with equal weights
+        // yields a balanced tree and thus the smallest average control block.
+        let builder =
+            TaprootBuilder::with_huffman_tree(leaves.iter().map(|leaf| (1, leaf.script.clone())))
+                .map_err(|e| format!("Failed to build script tree: {}", e))?;
+
+        let internal_key = match self.key_path_owner {
+            Some(principal) => keys[&principal],
+            None => XOnlyPublicKey::from_slice(&UNSPENDABLE_INTERNAL_KEY).unwrap(),
+        };
+
+        let secp256k1_engine = Secp256k1::new();
+        let spend_info = builder
+            .finalize(&secp256k1_engine, internal_key)
+            .map_err(|_| "Failed to finalize script tree".to_string())?;
+        let address = Address::p2tr_tweaked(spend_info.output_key(), ctx.bitcoin_network);
+
+        Ok(CompiledPolicy {
+            address,
+            spend_info,
+            leaves,
+        })
+    }
+}
+
+impl CompiledPolicy {
+    /// The Merkle root of the script tree, as expected by the BIP-341 signing API for key path
+    /// spends.
+    pub fn merkle_root_hash(&self) -> Vec<u8> {
+        self.spend_info
+            .merkle_root()
+            .map(|root| root.to_byte_array().to_vec())
+            .unwrap_or_default()
+    }
+
+    /// Returns the leaf at `leaf_index`.
+    pub fn leaf(&self, leaf_index: u32) -> Result<&Leaf, String> {
+        self.leaves
+            .get(leaf_index as usize)
+            .ok_or_else(|| format!("No leaf with index {}", leaf_index))
+    }
+
+    /// Builds the script path spend through the leaf at `leaf_index`.
+    ///
+    /// Signatures are only provided for keys of `signers`, and hashlocks are satisfied from
+    /// `preimages`. Returns an error if that is not enough to satisfy the leaf.
+    pub fn script_spend(
+        &self,
+        leaf_index: u32,
+        signers: &[Principal],
+        preimages: &[Vec<u8>],
+    ) -> Result<ScriptSpend, String> {
+        let leaf = self.leaf(leaf_index)?;
+
+        // Collect the witness in the order the script consumes it. Since the script
+        // consumes the stack from the top, the witness is reversed at the end.
+        let mut consumed = vec![];
+        for fragment in leaf.fragments.iter() {
+            match fragment {
+                Policy::Pk(principal) => {
+                    if !signers.contains(principal) {
+                        return Err(format!("Leaf requires a signature of {}", principal));
+                    }
+                    consumed.push(ScriptWitnessItem::Signature(derivation_path(*principal)));
+                }
+                Policy::Key(key) => {
+                    return Err(format!(
+                        "Leaf requires a signature of the external key {}",
+                        hex::encode(key)
+                    ));
+                }
+                Policy::Multi { threshold, keys } => {
+                    let mut remaining = *threshold;
+                    for principal in keys.iter() {
+                        if remaining > 0 && signers.contains(principal) {
+                            consumed
+                                .push(ScriptWitnessItem::Signature(derivation_path(*principal)));
+                            remaining -= 1;
+                        } else {
+                            consumed.push(ScriptWitnessItem::Data(vec![]));
+                        }
+                    }
+                    if remaining > 0 {
+                        return Err(format!(
+                            "Leaf requires {} more signature(s) from its multisig keys",
+                            remaining
+                        ));
+                    }
+                }
+                Policy::Sha256(hash) => {
+                    let preimage = preimages
+                        .iter()
+                        .find(|preimage| sha256::Hash::hash(preimage).as_byte_array() == &hash[..])
+                        .ok_or_else(|| {
+                            format!("Leaf requires the preimage of {}", hex::encode(hash))
+                        })?;
+                    consumed.push(ScriptWitnessItem::Data(preimage.clone()));
+                }
+                Policy::Older(_) | Policy::After(_) => {}
+                Policy::And(_) | Policy::Or(_) => unreachable!("leaves only contain fragments"),
+            }
+        }
+        consumed.reverse();
+
+        let control_block = self
+            .spend_info
+            .control_block(&(leaf.script.clone(), LeafVersion::TapScript))
+            .expect("leaf is part of the script tree");
+
+        let lock_time = match leaf.absolute_timelock() {
+            0 => LockTime::ZERO,
+            height => LockTime::from_height(height).expect("validated block height"),
+        };
+        let sequence = match leaf.relative_timelock() {
+            0 => Sequence::ENABLE_RBF_NO_LOCKTIME,
+            blocks => Sequence::from_height(blocks),
+        };
+
+        Ok(ScriptSpend {
+            leaf_script: leaf.script.clone(),
+            control_block,
+            witness: consumed,
+            sequence,
+            lock_time,
+        })
+    }
+}
+
+impl Leaf {
+    /// The number of confirmations an output needs before it can be spent through this leaf,
+    /// or zero if the leaf has no relative timelock.
+    pub fn relative_timelock(&self) -> u16 {
+        self.fragments
+            .iter()
+            .filter_map(|fragment| match fragment {
+                Policy::Older(blocks) => Some(*blocks),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// The block height after which this leaf becomes spendable, or zero if the leaf has no
+    /// absolute timelock.
+    pub fn absolute_timelock(&self) -> u32 {
+        self.fragments
+            .iter()
+            .filter_map(|fragment| match fragment {
+                Policy::After(height) => Some(*height),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// The derivation path of a principal's key, which is the same as the one of its P2TR account.
+fn derivation_path(principal: Principal) -> Vec<Vec<u8>> {
+    vec![principal.as_slice().to_vec()]
+}
+
+async fn get_x_only_public_key(ctx: &BitcoinContext, principal: Principal) -> XOnlyPublicKey {
+    let public_key = get_schnorr_public_key(ctx, derivation_path(principal)).await;
+    XOnlyPublicKey::from(PublicKey::from_slice(&public_key).unwrap())
+}
+
+/// Brings `policy` into disjunctive normal form: a list of alternatives, each of which is a
+/// list of fragments that must all be satisfied.
+fn conjunctions(policy: &Policy) -> Result<Vec<Vec<&Policy>>, String> {
+    let conjunctions = match policy {
+        Policy::And(policies) => {
+            if policies.is_empty() {
+                return Err("`and` needs at least one sub-policy".to_string());
+            }
+            let mut result = vec![vec![]];
+            for policy in policies {
+                let alternatives = conjunctions(policy)?;
+                result = result
+                    .iter()
+                    .flat_map(|conjunction: &Vec<&Policy>| {
+                        alternatives.iter().map(move |alternative| {
+                            [conjunction.clone(), alternative.clone()].concat()
+                        })
+                    })
+                    .collect();
+                if result.len() > MAX_LEAVES {
+                    break;
+                }
+            }
+            result
+        }
+        Policy::Or(policies) => {
+            if policies.is_empty() {
+                return Err("`or` needs at least one sub-policy".to_string());
+            }
+            let mut result = vec![];
+            for policy in policies {
+                result.extend(conjunctions(policy)?);
+                if result.len() > MAX_LEAVES {
+                    break;
+                }
+            }
+            result
+        }
+        fragment => vec![vec![fragment]],
+    };
+
+    if conjunctions.len() > MAX_LEAVES {
+        return Err(format!(
+            "Policy has more than {} spending paths",
+            MAX_LEAVES
+        ));
+    }
+    Ok(conjunctions)
+}
+
+fn validate_fragment(fragment: &Policy) -> Result<(), String> {
+    match fragment {
+        Policy::Multi { threshold, keys } => {
+            if keys.is_empty() || keys.len() > MAX_MULTI_KEYS {
+                return Err(format!(
+                    "`multi` needs between 1 and {} keys",
+                    MAX_MULTI_KEYS
+                ));
+            }
+            if *threshold == 0 || *threshold as usize > keys.len() {
+                return Err(
+                    "`multi` threshold must be between 1 and the number of keys".to_string()
+                );
+            }
+            if keys
+                .iter()
+                .enumerate()
+                .any(|(i, key)| keys[..i].contains(key))
+            {
+                return Err("`multi` keys must be distinct".to_string());
+            }
+        }
+        Policy::Older(blocks) => {
+            if *blocks == 0 {
+                return Err("`older` needs at least one block".to_string());
+            }
+        }
+        Policy::After(height) => {
+            if LockTime::from_height(*height).is_err() || *height == 0 {
+                return Err("`after` needs a block height between 1 and 499,999,999".to_string());
+            }
+        }
+        Policy::Sha256(hash) => {
+            if hash.len() != 32 {
+                return Err("`sha256` needs a 32-byte hash".to_string());
+            }
+        }
+        Policy::Key(key) => {
+            if XOnlyPublicKey::from_slice(key).is_err() {
+                return Err("`key` needs a 32-byte x-only public key".to_string());
+            }
+        }
+        Policy::Pk(_) | Policy::And(_) | Policy::Or(_) => {}
+    }
+    Ok(())
+}
+
+fn collect_principals(policy: &Policy, principals: &mut Vec<Principal>) {
+    match policy {
+        Policy::Pk(principal) => principals.push(*principal),
+        Policy::Multi { keys, .. } => principals.extend(keys.iter().copied()),
+        Policy::And(policies) | Policy::Or(policies) => {
+            for policy in policies {
+                collect_principals(policy, principals);
+            }
+        }
+        Policy::Key(_) | Policy::Older(_) | Policy::After(_) | Policy::Sha256(_) => {}
+    }
+}
+
+/// Compiles a conjunction of fragments into a tapscript. Every fragment but the last is
+/// compiled into its verifying form, so that only the last one leaves its result on the stack.
+fn leaf_script(conjunction: &[&Policy], keys: &BTreeMap<Principal, XOnlyPublicKey>) -> ScriptBuf {
+    let mut builder = Builder::new();
+    for (i, fragment) in conjunction.iter().enumerate() {
+        let last = i == conjunction.len() - 1;
+        builder = match fragment {
+            Policy::Pk(principal) => builder
+                .push_x_only_key(&keys[principal])
+                .push_opcode(if last { OP_CHECKSIG } else { OP_CHECKSIGVERIFY }),
+            Policy::Key(key) => builder
+                .push_x_only_key(&XOnlyPublicKey::from_slice(key).expect("validated key"))
+                .push_opcode(if last { OP_CHECKSIG } else { OP_CHECKSIGVERIFY }),
+            Policy::Multi {
+                threshold,
+                keys: principals,
+            } => {
+                for (j, principal) in principals.iter().enumerate() {
+                    builder = builder
+                        .push_x_only_key(&keys[principal])
+                        .push_opcode(if j == 0 { OP_CHECKSIG } else { OP_CHECKSIGADD });
+                }
+                builder.push_int(*threshold as i64).push_opcode(if last {
+                    OP_NUMEQUAL
+                } else {
+                    OP_NUMEQUALVERIFY
+                })
+            }
+            Policy::Older(blocks) => {
+                let builder = builder.push_int(*blocks as i64).push_opcode(OP_CSV);
+                if last {
+                    builder
+                } else {
+                    builder.push_opcode(OP_DROP)
+                }
+            }
+            Policy::After(height) => {
+                let builder = builder.push_int(*height as i64).push_opcode(OP_CLTV);
+                if last {
+                    builder
+                } else {
+                    builder.push_opcode(OP_DROP)
+                }
+            }
+            Policy::Sha256(hash) => builder
+                .push_opcode(OP_SIZE)
+                .push_int(32)
+                .push_opcode(OP_EQUALVERIFY)
+                .push_opcode(OP_SHA256)
+                .push_slice(<[u8; 32]>::try_from(hash.as_slice()).expect("validated hash length"))
+                .push_opcode(if last { OP_EQUAL } else { OP_EQUALVERIFY }),
+            Policy::And(_) | Policy::Or(_) => unreachable!("leaves only contain fragments"),
+        };
+    }
+    builder.into_script()
+}
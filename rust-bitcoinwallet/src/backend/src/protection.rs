@@ -0,0 +1,99 @@
+// This module keeps track of the outputs that must not be spent to fund ordinary payments and
+// fees, because they carry something worth more than their value: an inscribed sat or runes.
+// Spending such an output as a plain input would hand the collectible to whoever receives
+// the first output, or burn it.
+//
+// Outputs get protected automatically when the smart contract creates an inscription or runes
+// for a principal, and principals can protect and unprotect any outpoint themselves. Outputs
+// recorded as carrying runes (see `crate::runes`) are protected as well. Protected outputs
+// are still spent deliberately, by `send_utxo` and `transfer_rune`.
+
+use std::collections::BTreeSet;
+
+use bitcoin::OutPoint;
+use candid::Principal;
+
+use crate::state::{mutate_state, read_state};
+
+/// Upper bound on the number of outputs a principal can protect themselves. Outputs protected
+/// automatically count towards it, but are protected regardless, since they already exist.
+pub const MAX_PROTECTED_OUTPUTS: usize = 1_000;
+
+/// Protects the output at `outpoint` of `owner` from ordinary coin selection.
+pub fn protect_output(owner: Principal, outpoint: OutPoint) {
+    mutate_state(|state| {
+        state
+            .protected_outputs
+            .entry(owner)
+            .or_default()
+            .insert(outpoint.to_string())
+    });
+}
+
+/// Protects the output at `outpoint` on behalf of `owner`, unless they already protect
+/// [`MAX_PROTECTED_OUTPUTS`] outputs.
+pub fn request_protection(owner: Principal, outpoint: OutPoint) -> Result<(), String> {
+    let at_limit = read_state(|state| {
+        state
+            .protected_outputs
+            .get(&owner)
+            .is_some_and(|protected_outputs| {
+                protected_outputs.len() >= MAX_PROTECTED_OUTPUTS
+                    && !protected_outputs.contains(&outpoint.to_string())
+            })
+    });
+    if at_limit {
+        return Err(format!(
+            "Cannot protect more than {} outputs; unprotect some first",
+            MAX_PROTECTED_OUTPUTS
+        ));
+    }
+    protect_output(owner, outpoint);
+    Ok(())
+}
+
+/// Lifts the protection of the output at `outpoint` of `owner`, e.g. once it has been spent.
+///
+/// Outputs recorded as carrying runes stay protected until their runes are transferred.
+pub fn unprotect_output(owner: Principal, outpoint: OutPoint) {
+    mutate_state(|state| {
+        if let Some(protected_outputs) = state.protected_outputs.get_mut(&owner) {
+            protected_outputs.remove(&outpoint.to_string());
+        }
+    });
+}
+
+/// Whether the output at `outpoint` of `owner` is protected, either explicitly or because it
+/// is recorded as carrying runes.
+pub fn is_protected(owner: Principal, outpoint: &OutPoint) -> bool {
+    let outpoint = outpoint.to_string();
+    read_state(|state| {
+        state
+            .protected_outputs
+            .get(&owner)
+            .is_some_and(|protected_outputs| protected_outputs.contains(&outpoint))
+            || state
+                .rune_outputs
+                .get(&owner)
+                .is_some_and(|rune_outputs| rune_outputs.contains_key(&outpoint))
+    })
+}
+
+/// All protected outputs of `owner`, as `txid:vout`.
+pub fn protected_outputs(owner: Principal) -> BTreeSet<String> {
+    read_state(|state| {
+        let mut outpoints = state
+            .protected_outputs
+            .get(&owner)
+            .cloned()
+            .unwrap_or_default();
+        outpoints.extend(
+            state
+                .rune_outputs
+                .get(&owner)
+                .into_iter()
+                .flat_map(|rune_outputs| rune_outputs.keys().cloned()),
+        );
+        outpoints
+    })
+}
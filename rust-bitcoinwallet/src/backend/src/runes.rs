@@ -0,0 +1,686 @@
+// This module implements the parts of the Runes protocol needed to etch and mint runes.
+//
+// Runes are moved by runestones: OP_RETURN outputs starting with `OP_13`, followed by data
+// pushes whose concatenation is a sequence of LEB128-encoded integers. The integers form
+// tag/value pairs describing an etching of a new rune or a mint from an open etching, followed
+// by edicts that allocate the runes of the transaction's inputs to its outputs. Etching a
+// named rune additionally requires the etching transaction to spend a Taproot output through a
+// leaf that commits to the name, and that output must have been confirmed six blocks earlier.
+//
+// Runes that are not allocated by an edict go to the output named by the pointer, or to the
+// first output that is not an OP_RETURN output. Since the Bitcoin canister knows nothing about
+// runes, the smart contract keeps its own record of which outputs of its users carry which
+// runes, covering the runes it etched, minted or transferred itself.
+
+use std::collections::BTreeMap;
+
+use bitcoin::{
+    opcodes::all::{OP_PUSHNUM_13, OP_RETURN},
+    script::{Builder, PushBytes},
+    OutPoint, ScriptBuf,
+};
+use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::bitcoin_canister::{Network, Utxo};
+
+use crate::{
+    btc::outpoint,
+    state::{mutate_state, read_state},
+};
+
+/// Confirmations the commit output needs before the etching that reveals it is broadcast.
+pub const COMMIT_CONFIRMATIONS: u32 = 6;
+
+/// Upper bound on the number of decimals of a rune.
+const MAX_DIVISIBILITY: u8 = 38;
+
+/// Upper bound on the number of letters of a rune name. Longer names are reserved by the
+/// protocol or do not fit into 128 bits.
+const MAX_NAME_LENGTH: u32 = 26;
+
+/// Blocks after which the next shorter rune names unlock, a twelfth of the halving interval.
+const UNLOCK_INTERVAL: u32 = 17_500;
+
+// The smallest rune with 1 to 13 letters, `A` to `AAAAAAAAAAAAA`. Until runes are activated,
+// only names of 13 letters or more can be etched, and over the following twelve unlock
+// intervals the limit moves down linearly through these steps.
+const UNLOCK_STEPS: [u128; 13] = [
+    0,
+    26,
+    702,
+    18_278,
+    475_254,
+    12_356_630,
+    321_272_406,
+    8_353_082_582,
+    217_180_147_158,
+    5_646_683_826_134,
+    146_813_779_479_510,
+    3_817_158_266_467_286,
+    99_246_114_928_149_462,
+];
+
+/// Largest data push allowed in a standard OP_RETURN output.
+const MAX_PUSH_LENGTH: usize = 520;
+
+/// Largest OP_RETURN output script, including the opcodes, that nodes relay by default.
+const MAX_OP_RETURN_SIZE: usize = 83;
+
+// Tags of the runestone fields.
+const TAG_BODY: u128 = 0;
+const TAG_FLAGS: u128 = 2;
+const TAG_RUNE: u128 = 4;
+const TAG_PREMINE: u128 = 6;
+const TAG_CAP: u128 = 8;
+const TAG_AMOUNT: u128 = 10;
+const TAG_HEIGHT_START: u128 = 12;
+const TAG_HEIGHT_END: u128 = 14;
+const TAG_OFFSET_START: u128 = 16;
+const TAG_OFFSET_END: u128 = 18;
+const TAG_MINT: u128 = 20;
+const TAG_POINTER: u128 = 22;
+const TAG_DIVISIBILITY: u128 = 1;
+const TAG_SPACERS: u128 = 3;
+const TAG_SYMBOL: u128 = 5;
+
+// Bits of the flags field.
+const FLAG_ETCHING: u128 = 1 << 0;
+const FLAG_TERMS: u128 = 1 << 1;
+
+/// Identifies a rune by the block height and the index in the block of its etching.
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RuneId {
+    pub block: u64,
+    pub tx: u32,
+}
+
+/// Conditions under which anyone can mint an etched rune.
+#[derive(CandidType, Deserialize, Clone, Default)]
+pub struct Terms {
+    /// Amount of runes created by each mint.
+    pub amount: Option<u128>,
+    /// Maximum number of mints.
+    pub cap: Option<u128>,
+    /// Absolute block heights between which minting is open, end exclusive.
+    pub height_start: Option<u64>,
+    pub height_end: Option<u64>,
+    /// Block heights relative to the etching between which minting is open, end exclusive.
+    pub offset_start: Option<u64>,
+    pub offset_end: Option<u64>,
+}
+
+/// The definition of a new rune.
+#[derive(CandidType, Deserialize, Clone)]
+pub struct Etching {
+    /// Name of the rune: letters `A` to `Z`, optionally separated by `•` or `.`.
+    pub rune: String,
+    /// Number of decimals of the rune's amounts.
+    pub divisibility: Option<u8>,
+    /// A single character used as currency symbol.
+    pub symbol: Option<String>,
+    /// Amount of runes allocated to the etcher.
+    pub premine: Option<u128>,
+    /// Open minting terms, if anyone besides the etcher may create runes.
+    pub terms: Option<Terms>,
+}
+
+/// A rune etching started by a principal, see [`Etching`].
+#[derive(CandidType, Deserialize, Clone)]
+pub struct RuneEtching {
+    pub owner: Principal,
+    pub etching: Etching,
+    /// The transaction paying to the address that commits to the rune name.
+    pub commit_txid: String,
+    /// The value of the commit output, which covers the postage and the reveal fee.
+    pub commit_value: u64,
+    /// The etching transaction, once it has been broadcast.
+    pub reveal_txid: Option<String>,
+    /// The transaction returning the commit output to the etcher, if the etching was given up.
+    pub recovery_txid: Option<String>,
+    /// The id of the rune, once the etcher has confirmed where the etching got mined.
+    pub rune_id: Option<RuneId>,
+}
+
+/// Allocates `amount` runes of the rune `id` from the inputs to the output at index `output`.
+pub struct Edict {
+    pub id: RuneId,
+    pub amount: u128,
+    pub output: u32,
+}
+
+/// A runestone, the message of the Runes protocol carried by a transaction.
+#[derive(Default)]
+pub struct Runestone {
+    pub etching: Option<Etching>,
+    pub mint: Option<RuneId>,
+    pub edicts: Vec<Edict>,
+    /// The output receiving the runes that no edict allocates.
+    pub pointer: Option<u32>,
+}
+
+/// Runes carried by an output, by rune id.
+pub type RuneBalances = BTreeMap<RuneId, u128>;
+
+impl Etching {
+    /// Checks that the etching encodes to a valid runestone: the name is well-formed, the
+    /// divisibility and symbol are in range, and the total supply fits into 128 bits.
+    pub fn validate(&self) -> Result<(), String> {
+        self.spaced_rune()?;
+        if self.divisibility.unwrap_or_default() > MAX_DIVISIBILITY {
+            return Err(format!("Divisibility must be at most {}", MAX_DIVISIBILITY));
+        }
+        self.symbol()?;
+
+        // An etching whose supply overflows is a cenotaph, which burns the premine.
+        let terms = self.terms.clone().unwrap_or_default();
+        terms
+            .cap
+            .unwrap_or_default()
+            .checked_mul(terms.amount.unwrap_or_default())
+            .and_then(|mintable| mintable.checked_add(self.premine.unwrap_or_default()))
+            .ok_or("Total supply of the rune exceeds 2^128 - 1")?;
+        Ok(())
+    }
+
+    /// Checks that the name is unlocked at block `height` of `network`, see
+    /// [`minimum_rune`]. Shorter names unlock over time, so a name unlocked at some height
+    /// stays unlocked at all later heights.
+    pub fn check_unlocked(&self, network: Network, height: u32) -> Result<(), String> {
+        let (rune, _) = self.spaced_rune()?;
+        let minimum = minimum_rune(network, height);
+        if rune < minimum {
+            return Err(format!(
+                "Rune name {} is still locked at block height {}, the smallest unlocked name \
+                 is {}",
+                self.rune,
+                height,
+                rune_name(minimum)
+            ));
+        }
+        Ok(())
+    }
+
+    /// The commitment to the rune name that the etching transaction has to reveal: the name
+    /// as little-endian integer, without trailing zero bytes.
+    pub fn commitment(&self) -> Result<Vec<u8>, String> {
+        let (rune, _) = self.spaced_rune()?;
+        let bytes = rune.to_le_bytes();
+        let end = bytes
+            .iter()
+            .rposition(|byte| *byte != 0)
+            .map_or(0, |i| i + 1);
+        Ok(bytes[..end].to_vec())
+    }
+
+    // Parses the name into the rune, a modified base-26 integer, and the spacer bit field,
+    // whose bit `i` marks a spacer after the `i`-th letter.
+    fn spaced_rune(&self) -> Result<(u128, u32), String> {
+        let mut rune: u128 = 0;
+        let mut spacers: u32 = 0;
+        let mut letters: u32 = 0;
+        for c in self.rune.chars() {
+            match c {
+                'A'..='Z' => {
+                    if letters == MAX_NAME_LENGTH {
+                        return Err(format!(
+                            "Rune name must have at most {} letters",
+                            MAX_NAME_LENGTH
+                        ));
+                    }
+                    if letters > 0 {
+                        rune += 1;
+                    }
+                    rune = rune * 26 + (c as u128 - 'A' as u128);
+                    letters += 1;
+                }
+                '•' | '.' => {
+                    let flag = letters.checked_sub(1).map(|i| 1 << i).unwrap_or_default();
+                    if flag == 0 || spacers & flag != 0 {
+                        return Err("Spacers must separate letters".to_string());
+                    }
+                    spacers |= flag;
+                }
+                _ => return Err(format!("Invalid character `{}` in rune name", c)),
+            }
+        }
+        if letters == 0 || spacers >> (letters - 1) != 0 {
+            return Err("Rune name must start and end with a letter".to_string());
+        }
+        Ok((rune, spacers))
+    }
+
+    fn symbol(&self) -> Result<Option<char>, String> {
+        let Some(symbol) = &self.symbol else {
+            return Ok(None);
+        };
+        let mut chars = symbol.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(Some(c)),
+            _ => Err("Symbol must be a single character".to_string()),
+        }
+    }
+}
+
+impl Runestone {
+    /// Encodes the runestone as OP_RETURN script. Fails if the script exceeds the standard
+    /// size of OP_RETURN outputs.
+    pub fn encipher(&self) -> Result<ScriptBuf, String> {
+        let mut payload = vec![];
+
+        if let Some(etching) = &self.etching {
+            etching.validate()?;
+            let (rune, spacers) = etching.spaced_rune()?;
+
+            let mut flags = FLAG_ETCHING;
+            if etching.terms.is_some() {
+                flags |= FLAG_TERMS;
+            }
+            push_field(&mut payload, TAG_FLAGS, Some(flags));
+            push_field(&mut payload, TAG_RUNE, Some(rune));
+            push_field(
+                &mut payload,
+                TAG_DIVISIBILITY,
+                etching.divisibility.map(u128::from),
+            );
+            push_field(
+                &mut payload,
+                TAG_SPACERS,
+                Some(spacers as u128).filter(|s| *s != 0),
+            );
+            push_field(&mut payload, TAG_SYMBOL, etching.symbol()?.map(u128::from));
+            push_field(&mut payload, TAG_PREMINE, etching.premine);
+
+            if let Some(terms) = &etching.terms {
+                push_field(&mut payload, TAG_AMOUNT, terms.amount);
+                push_field(&mut payload, TAG_CAP, terms.cap);
+                push_field(
+                    &mut payload,
+                    TAG_HEIGHT_START,
+                    terms.height_start.map(u128::from),
+                );
+                push_field(
+                    &mut payload,
+                    TAG_HEIGHT_END,
+                    terms.height_end.map(u128::from),
+                );
+                push_field(
+                    &mut payload,
+                    TAG_OFFSET_START,
+                    terms.offset_start.map(u128::from),
+                );
+                push_field(
+                    &mut payload,
+                    TAG_OFFSET_END,
+                    terms.offset_end.map(u128::from),
+                );
+            }
+        }
+
+        if let Some(RuneId { block, tx }) = self.mint {
+            push_field(&mut payload, TAG_MINT, Some(block as u128));
+            push_field(&mut payload, TAG_MINT, Some(tx as u128));
+        }
+        push_field(&mut payload, TAG_POINTER, self.pointer.map(u128::from));
+
+        // Edicts come last, sorted by rune id, with each id encoded as difference to the
+        // previous one.
+        if !self.edicts.is_empty() {
+            push_varint(&mut payload, TAG_BODY);
+            let mut edicts: Vec<_> = self.edicts.iter().collect();
+            edicts.sort_by_key(|edict| edict.id);
+            let mut previous = RuneId { block: 0, tx: 0 };
+            for edict in edicts {
+                let block = edict.id.block - previous.block;
+                let tx = if block == 0 {
+                    edict.id.tx - previous.tx
+                } else {
+                    edict.id.tx
+                };
+                push_varint(&mut payload, block as u128);
+                push_varint(&mut payload, tx as u128);
+                push_varint(&mut payload, edict.amount);
+                push_varint(&mut payload, edict.output as u128);
+                previous = edict.id;
+            }
+        }
+
+        let mut builder = Builder::new()
+            .push_opcode(OP_RETURN)
+            .push_opcode(OP_PUSHNUM_13);
+        for chunk in payload.chunks(MAX_PUSH_LENGTH) {
+            builder = builder
+                .push_slice(<&PushBytes>::try_from(chunk).expect("pushes are at most 520 bytes"));
+        }
+        let script = builder.into_script();
+        // A transaction with a larger runestone would not be relayed, so this has to be checked
+        // before anything, e.g. the commit transaction of an etching, is broadcast.
+        if script.len() > MAX_OP_RETURN_SIZE {
+            return Err(format!(
+                "Runestone takes {} bytes, more than the {} bytes of a standard OP_RETURN output",
+                script.len(),
+                MAX_OP_RETURN_SIZE
+            ));
+        }
+        Ok(script)
+    }
+}
+
+/// The smallest rune that can be etched in a block at `height` of `network`.
+///
+/// Names of 13 letters or more can be etched from the start. At the activation height of
+/// runes, names start to unlock from `AAAAAAAAAAAAA` down, one letter per 17,500 blocks, until
+/// all names are unlocked after a halving interval.
+pub fn minimum_rune(network: Network, height: u32) -> u128 {
+    // The Bitcoin canister follows testnet4, where runes are active from the genesis block.
+    let start = match network {
+        Network::Mainnet => 840_000,
+        Network::Testnet | Network::Regtest => 0,
+    };
+    let offset = height.saturating_add(1);
+    if offset < start {
+        return UNLOCK_STEPS[12];
+    }
+    let progress = offset - start;
+    let length = 12u32.saturating_sub(progress / UNLOCK_INTERVAL) as usize;
+    if length == 0 {
+        return 0;
+    }
+    let (end, start) = (UNLOCK_STEPS[length - 1], UNLOCK_STEPS[length]);
+    let remainder = (progress % UNLOCK_INTERVAL) as u128;
+    start - (start - end) * remainder / UNLOCK_INTERVAL as u128
+}
+
+/// Records that the output at `outpoint` of `owner` carries the given runes.
+pub fn record_rune_output(owner: Principal, outpoint: OutPoint, balances: RuneBalances) {
+    if balances.values().all(|amount| *amount == 0) {
+        return;
+    }
+    mutate_state(|state| {
+        state
+            .rune_outputs
+            .entry(owner)
+            .or_default()
+            .insert(outpoint.to_string(), balances)
+    });
+}
+
+/// Forgets the runes of the outputs of `owner` that were spent at `outpoints`.
+pub fn remove_rune_outputs(owner: Principal, outpoints: &[OutPoint]) {
+    mutate_state(|state| {
+        if let Some(rune_outputs) = state.rune_outputs.get_mut(&owner) {
+            for outpoint in outpoints {
+                rune_outputs.remove(&outpoint.to_string());
+            }
+        }
+    });
+}
+
+/// The total balance of each rune over all recorded outputs of `owner`.
+pub fn rune_balances(owner: Principal) -> RuneBalances {
+    let mut totals = RuneBalances::new();
+    read_state(|state| {
+        for balances in state
+            .rune_outputs
+            .get(&owner)
+            .into_iter()
+            .flat_map(|o| o.values())
+        {
+            for (id, amount) in balances {
+                *totals.entry(*id).or_default() += amount;
+            }
+        }
+    });
+    totals
+}
+
+/// Splits `utxos` into the ones recorded as carrying runes of `owner`, together with their
+/// runes, and the ones that can be spent without moving any runes.
+pub fn partition_rune_utxos(
+    owner: Principal,
+    utxos: Vec<Utxo>,
+) -> (Vec<(Utxo, RuneBalances)>, Vec<Utxo>) {
+    read_state(|state| {
+        let rune_outputs = state.rune_outputs.get(&owner);
+        let mut rune_utxos = vec![];
+        let mut other_utxos = vec![];
+        for utxo in utxos {
+            match rune_outputs.and_then(|o| o.get(&outpoint(&utxo).to_string())) {
+                Some(balances) => rune_utxos.push((utxo, balances.clone())),
+                None => other_utxos.push(utxo),
+            }
+        }
+        (rune_utxos, other_utxos)
+    })
+}
+
+// The name of `rune` in modified base-26, without spacers: `A` to `Z`, then `AA` and so on.
+fn rune_name(rune: u128) -> String {
+    let mut letters = vec![];
+    let mut n = rune;
+    loop {
+        letters.push((b'A' + (n % 26) as u8) as char);
+        match (n / 26).checked_sub(1) {
+            Some(next) => n = next,
+            None => break,
+        }
+    }
+    letters.iter().rev().collect()
+}
+
+// Appends the tag/value pair to the payload, unless the field is absent.
+fn push_field(payload: &mut Vec<u8>, tag: u128, value: Option<u128>) {
+    if let Some(value) = value {
+        push_varint(payload, tag);
+        push_varint(payload, value);
+    }
+}
+
+// Appends `n` as LEB128 varint, seven bits per byte, least significant group first.
+fn push_varint(payload: &mut Vec<u8>, mut n: u128) {
+    while n >> 7 > 0 {
+        payload.push((n as u8 & 0x7f) | 0x80);
+        n >>= 7;
+    }
+    payload.push(n as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn etching(rune: &str) -> Etching {
+        Etching {
+            rune: rune.to_string(),
+            divisibility: None,
+            symbol: None,
+            premine: None,
+            terms: None,
+        }
+    }
+
+    // Reads a LEB128 varint from the start of `bytes`, returning it and its length.
+    fn read_varint(bytes: &[u8]) -> (u128, usize) {
+        let mut n = 0;
+        for (i, byte) in bytes.iter().enumerate() {
+            n |= u128::from(byte & 0x7f) << (7 * i);
+            if byte & 0x80 == 0 {
+                return (n, i + 1);
+            }
+        }
+        panic!("unterminated varint");
+    }
+
+    #[test]
+    fn enciphers_etching() {
+        let runestone = Runestone {
+            etching: Some(Etching {
+                symbol: Some("⧉".to_string()),
+                terms: Some(Terms {
+                    amount: Some(1),
+                    cap: Some(1_000),
+                    ..Default::default()
+                }),
+                ..etching("UNCOMMON•GOODS")
+            }),
+            ..Default::default()
+        };
+        // OP_RETURN OP_13 <23 bytes>: flags etching and terms, the rune, spacer bit 7, the
+        // symbol U+29C9, amount 1 and cap 1000.
+        assert_eq!(
+            hex::encode(runestone.encipher().unwrap()),
+            "6a5d17020304de8a85e1ebd881c41c03800105c9530a0108e807"
+        );
+    }
+
+    #[test]
+    fn enciphers_mint() {
+        let runestone = Runestone {
+            mint: Some(RuneId {
+                block: 840_000,
+                tx: 3,
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            hex::encode(runestone.encipher().unwrap()),
+            "6a5d0614c0a2331403"
+        );
+    }
+
+    #[test]
+    fn enciphers_edicts_with_pointer() {
+        let runestone = Runestone {
+            edicts: vec![
+                Edict {
+                    id: RuneId {
+                        block: 840_000,
+                        tx: 5,
+                    },
+                    amount: 7,
+                    output: 2,
+                },
+                Edict {
+                    id: RuneId {
+                        block: 840_000,
+                        tx: 3,
+                    },
+                    amount: 100,
+                    output: 1,
+                },
+            ],
+            pointer: Some(2),
+            ..Default::default()
+        };
+        // The pointer comes before the body; edicts are sorted by id and delta-encoded.
+        assert_eq!(
+            hex::encode(runestone.encipher().unwrap()),
+            "6a5d0d160200c0a23303640100020702"
+        );
+    }
+
+    #[test]
+    fn varints_round_trip() {
+        for n in [0, 1, 127, 128, 300, u64::MAX as u128, u128::MAX] {
+            let mut payload = vec![];
+            push_varint(&mut payload, n);
+            assert_eq!(read_varint(&payload), (n, payload.len()));
+        }
+        let mut payload = vec![];
+        push_varint(&mut payload, u128::MAX);
+        assert_eq!(payload.len(), 19);
+    }
+
+    #[test]
+    fn rune_names_round_trip() {
+        for (name, rune) in [
+            ("A", 0),
+            ("Z", 25),
+            ("AA", 26),
+            ("AZ", 51),
+            ("BA", 52),
+            ("AAA", 702),
+            ("UNCOMMONGOODS", 2_055_900_680_524_219_742),
+            ("AAAAAAAAAAAAA", UNLOCK_STEPS[12]),
+        ] {
+            assert_eq!(etching(name).spaced_rune().unwrap(), (rune, 0));
+            assert_eq!(rune_name(rune), name);
+        }
+        assert_eq!(
+            etching("UNCOMMON•GOODS").spaced_rune().unwrap(),
+            (2_055_900_680_524_219_742, 1 << 7)
+        );
+        assert_eq!(etching("A.B.C").spaced_rune().unwrap().1, 0b11);
+    }
+
+    #[test]
+    fn rejects_malformed_names() {
+        for name in ["", "•A", "A•", "A••B", "a", "A B"] {
+            assert!(etching(name).spaced_rune().is_err(), "{}", name);
+        }
+        assert!(etching(&"A".repeat(26)).spaced_rune().is_ok());
+        assert!(etching(&"A".repeat(27)).spaced_rune().is_err());
+    }
+
+    // A runestone whose script takes `size` bytes, 83 or 84: OP_RETURN, OP_13, OP_PUSHDATA1
+    // with its length, and a payload of a body tag and four edicts of the same rune.
+    fn runestone_of_size(size: usize) -> Runestone {
+        // Each edict takes 3 bytes besides its amount. Three amounts of u128::MAX take 19
+        // bytes each, and the last amount takes 9 or 10 bytes.
+        let last_amount = match size {
+            83 => 1 << 62,
+            84 => 1 << 63,
+            _ => unreachable!(),
+        };
+        let id = RuneId { block: 1, tx: 0 };
+        Runestone {
+            edicts: [u128::MAX, u128::MAX, u128::MAX, last_amount]
+                .into_iter()
+                .map(|amount| Edict {
+                    id,
+                    amount,
+                    output: 0,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn accepts_standard_op_return() {
+        assert_eq!(runestone_of_size(83).encipher().unwrap().len(), 83);
+    }
+
+    #[test]
+    fn rejects_oversized_op_return() {
+        assert_eq!(
+            runestone_of_size(84).encipher().unwrap_err(),
+            "Runestone takes 84 bytes, more than the 83 bytes of a standard OP_RETURN output"
+        );
+    }
+
+    #[test]
+    fn unlocks_names_over_time() {
+        assert_eq!(minimum_rune(Network::Mainnet, 0), UNLOCK_STEPS[12]);
+        assert_eq!(minimum_rune(Network::Mainnet, 839_998), UNLOCK_STEPS[12]);
+        assert_eq!(minimum_rune(Network::Mainnet, 839_999), UNLOCK_STEPS[12]);
+        // Names unlock gradually within each interval, starting at the activation height.
+        assert!(minimum_rune(Network::Mainnet, 840_000) < UNLOCK_STEPS[12]);
+        assert!(minimum_rune(Network::Mainnet, 840_000) > UNLOCK_STEPS[11]);
+        assert_eq!(
+            minimum_rune(Network::Mainnet, 839_999 + 17_500),
+            UNLOCK_STEPS[11]
+        );
+        assert_eq!(
+            minimum_rune(Network::Mainnet, 839_999 + 11 * 17_500),
+            UNLOCK_STEPS[1]
+        );
+        assert_eq!(minimum_rune(Network::Mainnet, 839_999 + 12 * 17_500), 0);
+        assert_eq!(minimum_rune(Network::Regtest, 209_999), 0);
+
+        let uncommon_goods = etching("UNCOMMON•GOODS");
+        assert!(uncommon_goods.check_unlocked(Network::Mainnet, 1).is_ok());
+        let short = etching("BTC");
+        assert!(short.check_unlocked(Network::Mainnet, 1_000_000).is_err());
+        assert!(short.check_unlocked(Network::Mainnet, 1_050_000).is_ok());
+        assert!(short.check_unlocked(Network::Regtest, 1_000).is_err());
+    }
+}
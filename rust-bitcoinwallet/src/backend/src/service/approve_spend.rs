@@ -0,0 +1,22 @@
+use ic_cdk::update;
+
+use crate::{
+    auth_guard,
+    multisig::{self, SpendProposal},
+    BTC_CONTEXT,
+};
+
+/// Approves a spend proposal of a shared wallet the caller co-owns.
+///
+/// Once the number of approvals meets the wallet's threshold, the smart contract signs the
+/// transaction with the keys of the approving co-owners, broadcasts it, and records its id in
+/// the returned proposal.
+#[update]
+pub async fn approve_spend(proposal_id: u64) -> Result<SpendProposal, String> {
+    // Calls to approve_spend need to be authenticated
+    auth_guard()?;
+
+    let ctx = BTC_CONTEXT.with(|ctx| ctx.get());
+
+    multisig::approve(&ctx, proposal_id, ic_cdk::api::msg_caller()).await
+}
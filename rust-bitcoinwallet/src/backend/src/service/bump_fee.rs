@@ -0,0 +1,105 @@
+use bitcoin::{consensus::serialize, Address, Txid};
+use ic_cdk::{
+    bitcoin_canister::{bitcoin_send_transaction, SendTransactionRequest},
+    update,
+};
+use std::str::FromStr;
+
+use crate::{
+    account::Account,
+    auth_guard,
+    btc::{transaction_fee, PrimaryOutput},
+    history::{
+        check_replacement_fee, forget_transaction, record_sent_transaction, replaceable_inputs,
+        replaceable_transaction,
+    },
+    state::read_state,
+    BTC_CONTEXT,
+};
+
+/// Replaces one of the caller's unconfirmed payments with one paying `new_fee_rate`, in
+/// satoshi per virtual byte, so that it confirms sooner.
+///
+/// The replacement spends all inputs of the original payment, in the same order, and makes
+/// the same payments. The higher fee is taken from the change, and further outputs of the
+/// account are added as inputs if the change does not cover it. A sweep has no change, so its
+/// destination receives less instead. The replacement has to satisfy the fee rules of BIP-125:
+/// it pays a higher fee rate than the original, and its fee exceeds the original one by at
+/// least 1 sat/vB of its own size. Only payments sent with `send_btc`, `send_many`, `sweep`,
+/// `bump_fee` or `cpfp` can be bumped.
+#[update]
+pub async fn bump_fee(txid: String, new_fee_rate: u64) -> Result<String, String> {
+    // Calls to bump_fee need to be authenticated
+    auth_guard()?;
+
+    let ctx = BTC_CONTEXT.with(|ctx| ctx.get());
+    let owner = ic_cdk::api::msg_caller();
+
+    let txid = Txid::from_str(&txid).map_err(|e| format!("Invalid txid: {:?}", e))?;
+    let original = replaceable_transaction(owner, txid)?;
+    let fee_per_byte = read_state(|state| state.fee_rate_limits).check(new_fee_rate)?;
+    let original_transaction = original.transaction();
+
+    let mut account =
+        Account::derive(&ctx, owner, original.account_id, original.address_type).await?;
+    account.load_receive_addresses(&ctx).await;
+
+    let (original_utxos, other_utxos) = replaceable_inputs(&ctx, &mut account, &original).await?;
+
+    let payments: Vec<PrimaryOutput> = original_transaction.output[..original.payments as usize]
+        .iter()
+        .map(|output| {
+            if output.script_pubkey.is_op_return() {
+                Ok(PrimaryOutput::OpReturn(output.script_pubkey.clone()))
+            } else {
+                Address::from_script(&output.script_pubkey, ctx.bitcoin_network)
+                    .map(|address| PrimaryOutput::Address(address, output.value.to_sat()))
+                    .map_err(|e| format!("Unsupported output: {:?}", e))
+            }
+        })
+        .collect::<Result<_, _>>()?;
+
+    let (transaction, prevouts) = if payments.is_empty() {
+        // A sweep pays whatever the fee leaves to its only output.
+        let destination = Address::from_script(
+            &original_transaction.output[0].script_pubkey,
+            ctx.bitcoin_network,
+        )
+        .map_err(|e| format!("Unsupported output: {:?}", e))?;
+        account
+            .build_sweep_transaction(&ctx, &original_utxos, &destination, fee_per_byte)
+            .await?
+    } else {
+        account
+            .build_transaction_spending(
+                &ctx,
+                &original_utxos,
+                &other_utxos,
+                &payments,
+                fee_per_byte,
+            )
+            .await?
+    };
+
+    let vsize = account.predicted_vsize(&transaction);
+    check_replacement_fee(&original, transaction_fee(&transaction, &prevouts), vsize)?;
+
+    let signed_transaction = account
+        .sign_transaction(&ctx, transaction, prevouts.as_slice())
+        .await;
+
+    // Send the transaction to the Bitcoin network.
+    bitcoin_send_transaction(&SendTransactionRequest {
+        network: ctx.network,
+        transaction: serialize(&signed_transaction),
+    })
+    .await
+    .map_err(|e| format!("Failed to send transaction: {:?}", e))?;
+
+    let replacement = signed_transaction.compute_txid();
+    record_sent_transaction(&account, &signed_transaction, &prevouts, payments.len());
+    // The original can no longer confirm.
+    forget_transaction(owner, txid);
+
+    Ok(replacement.to_string())
+}
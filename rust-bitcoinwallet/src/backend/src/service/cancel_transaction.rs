@@ -0,0 +1,84 @@
+use bitcoin::{consensus::serialize, Txid};
+use ic_cdk::{
+    bitcoin_canister::{bitcoin_send_transaction, SendTransactionRequest},
+    update,
+};
+use std::str::FromStr;
+
+use crate::{
+    account::Account,
+    auth_guard,
+    btc::{get_fee_per_byte, transaction_fee},
+    history::{
+        check_replacement_fee, forget_transaction, min_replacement_fee, record_sent_transaction,
+        replaceable_inputs, replaceable_transaction,
+    },
+    BTC_CONTEXT,
+};
+
+/// Cancels one of the caller's unconfirmed payments by replacing it with a transaction that
+/// sends all of its inputs back to the main address of the account it was sent from.
+///
+/// The replacement pays the current fee rate, or just enough more than the original to
+/// satisfy the fee rules of BIP-125, whichever is higher. Once it confirms, the original
+/// payment can no longer confirm. Only payments sent with `send_btc`, `send_many`, `sweep`,
+/// `bump_fee` or `cpfp` can be cancelled, and a cancellation can itself be bumped with
+/// `bump_fee`.
+#[update]
+pub async fn cancel_transaction(txid: String) -> Result<String, String> {
+    // Calls to cancel_transaction need to be authenticated
+    auth_guard()?;
+
+    let ctx = BTC_CONTEXT.with(|ctx| ctx.get());
+    let owner = ic_cdk::api::msg_caller();
+
+    let txid = Txid::from_str(&txid).map_err(|e| format!("Invalid txid: {:?}", e))?;
+    let original = replaceable_transaction(owner, txid)?;
+
+    let mut account =
+        Account::derive(&ctx, owner, original.account_id, original.address_type).await?;
+    account.load_receive_addresses(&ctx).await;
+
+    let (original_utxos, _) = replaceable_inputs(&ctx, &mut account, &original).await?;
+
+    let fee_per_byte = get_fee_per_byte(&ctx).await;
+    let (mut transaction, mut prevouts) = account
+        .build_sweep_transaction(&ctx, &original_utxos, &account.address, fee_per_byte)
+        .await?;
+
+    // The size of a sweep does not depend on its fee, so a single rebuild at the fee rate
+    // that pays the minimum fee of a replacement suffices.
+    let vsize = account.predicted_vsize(&transaction);
+    let min_fee = min_replacement_fee(&original, vsize);
+    if transaction_fee(&transaction, &prevouts) < min_fee {
+        (transaction, prevouts) = account
+            .build_sweep_transaction(
+                &ctx,
+                &original_utxos,
+                &account.address,
+                (min_fee * 1000).div_ceil(vsize),
+            )
+            .await?;
+    }
+    check_replacement_fee(&original, transaction_fee(&transaction, &prevouts), vsize)?;
+
+    let signed_transaction = account
+        .sign_transaction(&ctx, transaction, prevouts.as_slice())
+        .await;
+
+    // Send the transaction to the Bitcoin network.
+    bitcoin_send_transaction(&SendTransactionRequest {
+        network: ctx.network,
+        transaction: serialize(&signed_transaction),
+    })
+    .await
+    .map_err(|e| format!("Failed to send transaction: {:?}", e))?;
+
+    // The cancellation is a sweep to the account itself, which bump_fee can replace again.
+    let replacement = signed_transaction.compute_txid();
+    record_sent_transaction(&account, &signed_transaction, &prevouts, 0);
+    // The original can no longer confirm.
+    forget_transaction(owner, txid);
+
+    Ok(replacement.to_string())
+}
@@ -0,0 +1,114 @@
+use bitcoin::{consensus::serialize, Address};
+use candid::Principal;
+use ic_cdk::{
+    bitcoin_canister::{
+        bitcoin_get_utxos, bitcoin_send_transaction, GetUtxosRequest, SendTransactionRequest,
+    },
+    update,
+};
+use std::str::FromStr;
+
+use crate::{
+    auth_guard, btc::get_fee_per_byte, p2tr, schnorr::sign_with_schnorr, state::read_state,
+    BTC_CONTEXT,
+};
+
+/// Sends all matured funds of `owner`'s inheritance account to the specified destination.
+///
+/// Only the heir of the plan can call this. An output has matured once it has not moved for
+/// the inactivity period of the plan; outputs that have not matured yet stay where they are.
+/// The funds are spent through the heir's leaf of the script tree, with the relative
+/// timelock encoded in the sequence of every input.
+#[update]
+pub async fn claim_inheritance(
+    owner: Principal,
+    destination_address: String,
+) -> Result<String, String> {
+    // Calls to claim_inheritance need to be authenticated
+    auth_guard()?;
+
+    let ctx = BTC_CONTEXT.with(|ctx| ctx.get());
+
+    // Parse and validate the destination address. The address type needs to be
+    // valid for the Bitcoin network we are on.
+    let dst_address = Address::from_str(&destination_address)
+        .map_err(|e| format!("Invalid destination address: {}", e))?
+        .require_network(ctx.bitcoin_network)
+        .map_err(|e| format!("Address not valid for network: {}", e))?;
+
+    let heir = ic_cdk::api::msg_caller();
+
+    let plan = read_state(|state| state.inheritance_plans.get(&owner).cloned())
+        .ok_or("No inheritance plan set up")?;
+    if plan.heir != heir {
+        return Err("Only the heir can claim the inheritance".to_string());
+    }
+
+    let compiled = plan.policy_account(owner).compile(&ctx).await?;
+    let own_address = &compiled.address;
+    let script_spend = compiled.script_spend(0, &[heir], &[])?;
+
+    // Get all UTXOs for the inheritance account.
+    // Note that pagination may have to be used to get all UTXOs for the given address.
+    // For the sake of simplicity, it is assumed here that the `utxo` field in the response
+    // contains all UTXOs.
+    let utxos_response = bitcoin_get_utxos(&GetUtxosRequest {
+        address: own_address.to_string(),
+        network: ctx.network,
+        filter: None,
+    })
+    .await
+    .map_err(|e| format!("Failed to get UTXOs: {:?}", e))?;
+
+    // The transaction can at the earliest be mined in the block after the tip.
+    let next_height = utxos_response.tip_height + 1;
+    let inactivity_blocks = plan.inactivity_blocks as u32;
+    let (matured, pending): (Vec<_>, Vec<_>) = utxos_response
+        .utxos
+        .into_iter()
+        .partition(|utxo| next_height - utxo.height >= inactivity_blocks);
+
+    if matured.is_empty() {
+        return match pending.iter().map(|utxo| utxo.height).min() {
+            Some(height) => Err(format!(
+                "Inheritance can be claimed from block {}, current height is {}",
+                height + inactivity_blocks,
+                utxos_response.tip_height
+            )),
+            None => Err("No UTXOs available for spending".to_string()),
+        };
+    }
+
+    let fee_per_byte = get_fee_per_byte(&ctx).await;
+    let (transaction, prevouts) = p2tr::build_sweep_transaction(
+        &ctx,
+        own_address,
+        &matured,
+        &[],
+        &dst_address,
+        fee_per_byte,
+        Some(&script_spend),
+    )
+    .await?;
+
+    let signed_transaction = p2tr::sign_transaction_script_spend(
+        &ctx,
+        own_address,
+        transaction,
+        prevouts.as_slice(),
+        &script_spend,
+        sign_with_schnorr,
+    )
+    .await;
+
+    // Send the transaction to the Bitcoin network.
+    bitcoin_send_transaction(&SendTransactionRequest {
+        network: ctx.network,
+        transaction: serialize(&signed_transaction),
+    })
+    .await
+    .map_err(|e| format!("Failed to send transaction: {:?}", e))?;
+
+    // Return the transaction ID.
+    Ok(signed_transaction.compute_txid().to_string())
+}
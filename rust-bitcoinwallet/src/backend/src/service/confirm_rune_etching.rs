@@ -0,0 +1,99 @@
+use bitcoin::{OutPoint, Txid};
+use ic_cdk::{
+    bitcoin_canister::{bitcoin_get_utxos, GetUtxosRequest},
+    update,
+};
+use std::str::FromStr;
+
+use crate::{
+    account::{Account, AddressType, DEFAULT_ACCOUNT},
+    auth_guard,
+    btc::outpoint,
+    runes::{record_rune_output, RuneBalances, RuneEtching, RuneId},
+    state::{mutate_state, read_state},
+    BTC_CONTEXT,
+};
+
+/// Sets the id of a revealed rune etching, once its etching transaction has been mined.
+///
+/// The rune id consists of the block height and the index of the etching transaction in that
+/// block. The smart contract checks the block height against the output the etching
+/// transaction sent to the caller's P2TR address, but cannot look up the index itself. Only
+/// the etcher can call this, and only once. Afterwards, the premine counts towards the
+/// caller's rune balance and can be moved with `transfer_rune`. Returns the updated etching.
+#[update]
+pub async fn confirm_rune_etching(id: u64, rune_id: RuneId) -> Result<RuneEtching, String> {
+    // Calls to confirm_rune_etching need to be authenticated
+    auth_guard()?;
+
+    let ctx = BTC_CONTEXT.with(|ctx| ctx.get());
+    let owner = ic_cdk::api::msg_caller();
+
+    let rune_etching = read_state(|state| state.rune_etchings.get(&id).cloned())
+        .ok_or_else(|| format!("No rune etching with id {}", id))?;
+    if rune_etching.owner != owner {
+        return Err("Only the etcher can confirm a rune etching".to_string());
+    }
+    let Some(reveal_txid) = &rune_etching.reveal_txid else {
+        return Err("Rune etching has not been revealed yet".to_string());
+    };
+    if let Some(rune_id) = rune_etching.rune_id {
+        return Err(format!(
+            "Rune etching was confirmed as {}:{}",
+            rune_id.block, rune_id.tx
+        ));
+    }
+
+    // The etching transaction sends the premine to its first output after the runestone, at
+    // the caller's P2TR address. That output is protected if it holds a premine, so it is
+    // still there; otherwise, it may have been spent already.
+    // Note that pagination may have to be used to get all UTXOs for the given address.
+    // For the sake of simplicity, it is assumed here that the `utxo` field in the response
+    // contains all UTXOs.
+    let premine_outpoint = OutPoint::new(Txid::from_str(reveal_txid).unwrap(), 1);
+    let premine = rune_etching.etching.premine.unwrap_or_default();
+    let account = Account::derive(&ctx, owner, DEFAULT_ACCOUNT, AddressType::P2tr).await?;
+    let utxos = bitcoin_get_utxos(&GetUtxosRequest {
+        address: account.address.to_string(),
+        network: ctx.network,
+        filter: None,
+    })
+    .await
+    .map_err(|e| format!("Failed to get UTXOs: {:?}", e))?
+    .utxos;
+    match utxos.iter().find(|utxo| outpoint(utxo) == premine_outpoint) {
+        Some(utxo) if u64::from(utxo.height) != rune_id.block => {
+            return Err(format!(
+                "Etching transaction {} was mined at height {}, not {}",
+                reveal_txid, utxo.height, rune_id.block
+            ));
+        }
+        None if premine > 0 => {
+            return Err(format!(
+                "Etching transaction {} is not confirmed yet",
+                reveal_txid
+            ));
+        }
+        _ => {}
+    }
+
+    // The etching may have been confirmed while the UTXOs were looked up.
+    let rune_etching = mutate_state(|state| {
+        let rune_etching = state.rune_etchings.get_mut(&id).unwrap();
+        if let Some(rune_id) = rune_etching.rune_id {
+            return Err(format!(
+                "Rune etching was confirmed as {}:{}",
+                rune_id.block, rune_id.tx
+            ));
+        }
+        rune_etching.rune_id = Some(rune_id);
+        Ok(rune_etching.clone())
+    })?;
+    record_rune_output(
+        owner,
+        premine_outpoint,
+        RuneBalances::from([(rune_id, premine)]),
+    );
+
+    Ok(rune_etching)
+}
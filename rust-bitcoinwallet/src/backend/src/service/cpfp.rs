@@ -0,0 +1,164 @@
+use bitcoin::{
+    consensus::{deserialize, serialize},
+    Transaction, Txid,
+};
+use ic_cdk::{
+    bitcoin_canister::{bitcoin_send_transaction, SendTransactionRequest},
+    update,
+};
+use std::str::FromStr;
+
+use crate::{
+    account::{Account, AccountId, AddressType, DEFAULT_ACCOUNT},
+    auth_guard,
+    btc::{child_fee, outpoint},
+    coin_control::is_spendable,
+    history::{
+        descendant_fee, forget_transaction, record_child_transaction, replaceable_transaction,
+    },
+    state::read_state,
+    BTC_CONTEXT,
+};
+
+/// An unconfirmed transaction the smart contract did not send itself, e.g. an incoming
+/// payment.
+#[derive(candid::CandidType, candid::Deserialize)]
+pub struct CpfpParent {
+    /// The transaction, consensus-encoded.
+    pub transaction: Vec<u8>,
+    /// The fee it pays in satoshi, e.g. as shown by a block explorer.
+    pub fee: u64,
+}
+
+/// Speeds up the unconfirmed transaction `txid` by spending its outputs that pay the caller in
+/// a child transaction, whose fee lifts both together to `target_fee_rate`, in satoshi per
+/// virtual byte (child-pays-for-parent).
+///
+/// The child sends the outputs, whether an incoming payment or the change of an own payment,
+/// back to the main address of the account, minus its fee. Payments sent by the smart
+/// contract are known to it. For any other transaction, `parent` has to provide the
+/// transaction and its fee, since the smart contract cannot look up unconfirmed transactions,
+/// and the account and address type select the account it pays, defaulting to the default
+/// account and P2TR. The child can be bumped and cancelled like any other payment.
+#[update]
+pub async fn cpfp(
+    txid: String,
+    target_fee_rate: u64,
+    parent: Option<CpfpParent>,
+    address_type: Option<AddressType>,
+    account: Option<AccountId>,
+) -> Result<String, String> {
+    // Calls to cpfp need to be authenticated
+    auth_guard()?;
+
+    let ctx = BTC_CONTEXT.with(|ctx| ctx.get());
+    let owner = ic_cdk::api::msg_caller();
+
+    let txid = Txid::from_str(&txid).map_err(|e| format!("Invalid txid: {:?}", e))?;
+    let fee_rate_limits = read_state(|state| state.fee_rate_limits);
+    let fee_per_byte = fee_rate_limits.check(target_fee_rate)?;
+
+    let (parent_transaction, parent_fee, account_id, address_type) = match parent {
+        Some(parent) => {
+            let transaction: Transaction = deserialize(&parent.transaction)
+                .map_err(|e| format!("Invalid transaction: {:?}", e))?;
+            if transaction.compute_txid() != txid {
+                return Err(format!("The given transaction is not {}", txid));
+            }
+            (
+                transaction,
+                parent.fee,
+                account.unwrap_or(DEFAULT_ACCOUNT),
+                address_type.unwrap_or_default(),
+            )
+        }
+        None => {
+            let sent_transaction = replaceable_transaction(owner, txid)?;
+            if descendant_fee(owner, txid) > 0 {
+                return Err(format!(
+                    "Transaction {} already has a child; use bump_fee instead",
+                    txid
+                ));
+            }
+            (
+                sent_transaction.transaction(),
+                sent_transaction.fee,
+                sent_transaction.account_id,
+                sent_transaction.address_type,
+            )
+        }
+    };
+
+    let mut account = Account::derive(&ctx, owner, account_id, address_type).await?;
+    account.load_receive_addresses(&ctx).await;
+
+    // The Bitcoin API only knows confirmed outputs, so the parent is confirmed once its
+    // outputs show up among the account's UTXOs.
+    let confirmed_utxos = account.get_utxos(&ctx).await?;
+    if confirmed_utxos
+        .iter()
+        .any(|utxo| outpoint(utxo).txid == txid)
+    {
+        forget_transaction(owner, txid);
+        return Err(format!("Transaction {} is already confirmed", txid));
+    }
+
+    let parent_utxos: Vec<_> = account
+        .unconfirmed_utxos(&parent_transaction)
+        .into_iter()
+        .filter(|utxo| is_spendable(owner, &outpoint(utxo)))
+        .collect();
+    if parent_utxos.is_empty() {
+        return Err(format!(
+            "Transaction {} pays none of the account's addresses",
+            txid
+        ));
+    }
+
+    // Predict the size of the child, then size its fee so that the package pays the target fee
+    // rate. The size of a transaction without change does not depend on its fee.
+    let (transaction, _) = account
+        .build_sweep_transaction(&ctx, &parent_utxos, &account.address, fee_per_byte)
+        .await?;
+    let child_vsize = account.predicted_vsize(&transaction);
+    let fee = child_fee(
+        parent_fee,
+        parent_transaction.vsize() as u64,
+        child_vsize,
+        fee_per_byte,
+    );
+    if fee == 0 {
+        return Err(format!(
+            "Transaction {} already pays at least {} sat/vB",
+            txid, target_fee_rate
+        ));
+    }
+    // The child has to pay the minimum fee rate for itself as well.
+    let fee = fee.max(child_vsize * fee_rate_limits.min_sat_per_vbyte);
+    let (transaction, prevouts) = account
+        .build_sweep_transaction(
+            &ctx,
+            &parent_utxos,
+            &account.address,
+            (fee * 1000).div_ceil(child_vsize),
+        )
+        .await?;
+
+    let signed_transaction = account
+        .sign_transaction(&ctx, transaction, prevouts.as_slice())
+        .await;
+
+    // Send the transaction to the Bitcoin network.
+    bitcoin_send_transaction(&SendTransactionRequest {
+        network: ctx.network,
+        transaction: serialize(&signed_transaction),
+    })
+    .await
+    .map_err(|e| format!("Failed to send transaction: {:?}", e))?;
+
+    // The child can be bumped or cancelled like any other payment, and a replacement of the
+    // parent has to pay for it as well.
+    record_child_transaction(&account, &signed_transaction, &prevouts, parent_utxos);
+
+    Ok(signed_transaction.compute_txid().to_string())
+}
@@ -0,0 +1,42 @@
+use ic_cdk::update;
+
+use crate::{
+    account::{AccountId, DEFAULT_ACCOUNT, MAX_ACCOUNTS, MAX_LABEL_LENGTH},
+    auth_guard,
+    state::mutate_state,
+};
+
+/// Creates a new account for the caller with the given label and returns its id.
+///
+/// Every account has its own keys, and thus its own address of every address type.
+#[update]
+pub fn create_account(label: String) -> Result<AccountId, String> {
+    // Calls to create_account need to be authenticated
+    auth_guard()?;
+
+    if label.len() > MAX_LABEL_LENGTH {
+        return Err(format!(
+            "Label must be at most {} bytes long",
+            MAX_LABEL_LENGTH
+        ));
+    }
+
+    let caller = ic_cdk::api::msg_caller();
+
+    mutate_state(|state| {
+        let accounts = state.accounts.entry(caller).or_default();
+
+        // The default account always exists, whether or not it has been labeled.
+        let count = accounts.len() + usize::from(!accounts.contains_key(&DEFAULT_ACCOUNT));
+        if count >= MAX_ACCOUNTS {
+            return Err(format!("At most {} accounts are supported", MAX_ACCOUNTS));
+        }
+
+        let id = accounts
+            .last_key_value()
+            .map_or(DEFAULT_ACCOUNT, |(id, _)| *id)
+            + 1;
+        accounts.insert(id, label);
+        Ok(id)
+    })
+}
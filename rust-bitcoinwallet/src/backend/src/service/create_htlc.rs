@@ -0,0 +1,35 @@
+use ic_cdk::update;
+
+use crate::{auth_guard, htlc::Htlc, state::mutate_state};
+
+/// Creates an HTLC from the caller to the holder of `counterparty_key` and returns its id.
+///
+/// The counterparty can claim the funds sent to the HTLC's address by revealing the preimage
+/// of `payment_hash` and signing with the 32-byte x-only public key `counterparty_key` of its
+/// own wallet, see `get_htlc` for the leaf it spends through. The caller can take them back
+/// in any block after height `timeout`, that is from block `timeout + 1` on, since the refund
+/// leaf checks the lock time with `OP_CHECKLOCKTIMEVERIFY`.
+#[update]
+pub fn create_htlc(
+    counterparty_key: Vec<u8>,
+    payment_hash: Vec<u8>,
+    timeout: u32,
+) -> Result<u64, String> {
+    // Calls to create_htlc need to be authenticated
+    auth_guard()?;
+
+    let htlc = Htlc {
+        owner: ic_cdk::api::msg_caller(),
+        counterparty_key,
+        payment_hash,
+        timeout,
+    };
+    htlc.validate()?;
+
+    Ok(mutate_state(|state| {
+        let id = state.next_htlc_id;
+        state.next_htlc_id += 1;
+        state.htlcs.insert(id, htlc);
+        id
+    }))
+}
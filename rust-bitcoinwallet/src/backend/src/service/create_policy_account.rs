@@ -0,0 +1,36 @@
+use candid::Principal;
+use ic_cdk::update;
+
+use crate::{
+    auth_guard,
+    policy::{Policy, PolicyAccount},
+    state::mutate_state,
+};
+
+/// Creates a P2TR account defined by a spending policy and returns its id.
+///
+/// The caller becomes the owner of the account. If `key_path_owner` is given, that principal's
+/// key is the internal key and can spend the account through the key path; otherwise the
+/// account can only be spent through one of the leaves compiled from `policy`.
+#[update]
+pub fn create_policy_account(
+    policy: Policy,
+    key_path_owner: Option<Principal>,
+) -> Result<u64, String> {
+    // Calls to create_policy_account need to be authenticated
+    auth_guard()?;
+
+    let account = PolicyAccount {
+        owner: ic_cdk::api::msg_caller(),
+        key_path_owner,
+        policy,
+    };
+    account.validate()?;
+
+    Ok(mutate_state(|state| {
+        let id = state.next_policy_account_id;
+        state.next_policy_account_id += 1;
+        state.policy_accounts.insert(id, account);
+        id
+    }))
+}
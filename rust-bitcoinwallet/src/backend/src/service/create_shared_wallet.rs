@@ -0,0 +1,26 @@
+use candid::Principal;
+use ic_cdk::update;
+
+use crate::{auth_guard, multisig::SharedWallet, state::mutate_state};
+
+/// Creates a wallet shared by `co_owners`, of which `threshold` need to approve every spend,
+/// and returns its id. The caller must be one of the co-owners.
+#[update]
+pub fn create_shared_wallet(co_owners: Vec<Principal>, threshold: u32) -> Result<u64, String> {
+    // Calls to create_shared_wallet need to be authenticated
+    auth_guard()?;
+
+    let wallet = SharedWallet {
+        creator: ic_cdk::api::msg_caller(),
+        co_owners,
+        threshold,
+    };
+    wallet.validate()?;
+
+    Ok(mutate_state(|state| {
+        let id = state.next_shared_wallet_id;
+        state.next_shared_wallet_id += 1;
+        state.shared_wallets.insert(id, wallet);
+        id
+    }))
+}
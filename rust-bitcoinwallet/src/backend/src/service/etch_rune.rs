@@ -0,0 +1,110 @@
+use bitcoin::consensus::serialize;
+use ic_cdk::{
+    bitcoin_canister::{bitcoin_send_transaction, SendTransactionRequest},
+    update,
+};
+
+use crate::{
+    account::{Account, AddressType, DEFAULT_ACCOUNT},
+    auth_guard,
+    btc::{dust_limit, get_fee_per_byte, PrimaryOutput},
+    ordinals,
+    runes::{Etching, RuneEtching, Runestone},
+    state::mutate_state,
+    BTC_CONTEXT,
+};
+
+/// Starts etching a new rune and returns the id of the etching.
+///
+/// Etching a named rune takes two transactions. This call broadcasts the commit transaction,
+/// funded from the caller's default P2TR account, which pays to a Taproot address committing
+/// to the rune name. Once the commit transaction has six confirmations,
+/// `reveal_rune_etching` broadcasts the etching itself. The commit output covers the fee of
+/// the etching transaction at the current fee rate, which is the fee the etching transaction
+/// pays later on. `recover_rune_commit` returns the commit output instead.
+///
+/// The name must be long enough to be unlocked at the current block height, which is checked
+/// before anything is broadcast, and must not have been etched before, which is not checked by
+/// the smart contract.
+#[update]
+pub async fn etch_rune(etching: Etching) -> Result<u64, String> {
+    // Calls to etch_rune need to be authenticated
+    auth_guard()?;
+
+    let runestone = Runestone {
+        etching: Some(etching.clone()),
+        ..Default::default()
+    }
+    .encipher()?;
+
+    let ctx = BTC_CONTEXT.with(|ctx| ctx.get());
+    let owner = ic_cdk::api::msg_caller();
+
+    let mut account = Account::derive(&ctx, owner, DEFAULT_ACCOUNT, AddressType::P2tr).await?;
+    account.load_receive_addresses(&ctx).await;
+
+    // Get all UTXOs of the account's main and receive addresses, except protected and frozen
+    // ones.
+    let own_utxos = account.get_spendable_utxos(&ctx).await?;
+
+    if own_utxos.is_empty() {
+        return Err("No UTXOs available for spending".to_string());
+    }
+
+    // The etching can at the earliest be mined in the block after the tip, and names only
+    // unlock over time.
+    etching.check_unlocked(ctx.network, account.tip_height() + 1)?;
+
+    let commit = ordinals::commit_address(&ctx, owner, &[etching.commitment()?]).await?;
+
+    // The commit output has to cover the postage of the output receiving the premine and the
+    // fee of the etching transaction.
+    let fee_per_byte = get_fee_per_byte(&ctx).await;
+    let reveal_fee = ordinals::reveal_fee(
+        &ctx,
+        &commit,
+        &[PrimaryOutput::OpReturn(runestone)],
+        &account.address,
+        fee_per_byte,
+    )
+    .await?;
+    let commit_value = dust_limit(&account.address) + reveal_fee;
+
+    let (transaction, prevouts) = account
+        .build_transaction(
+            &ctx,
+            &own_utxos,
+            &[PrimaryOutput::Address(commit.address.clone(), commit_value)],
+            fee_per_byte,
+        )
+        .await?;
+    let signed_transaction = account
+        .sign_transaction(&ctx, transaction, prevouts.as_slice())
+        .await;
+
+    // Send the transaction to the Bitcoin network.
+    bitcoin_send_transaction(&SendTransactionRequest {
+        network: ctx.network,
+        transaction: serialize(&signed_transaction),
+    })
+    .await
+    .map_err(|e| format!("Failed to send transaction: {:?}", e))?;
+
+    Ok(mutate_state(|state| {
+        let id = state.next_rune_etching_id;
+        state.next_rune_etching_id += 1;
+        state.rune_etchings.insert(
+            id,
+            RuneEtching {
+                owner,
+                etching,
+                commit_txid: signed_transaction.compute_txid().to_string(),
+                commit_value,
+                reveal_txid: None,
+                recovery_txid: None,
+                rune_id: None,
+            },
+        );
+        id
+    }))
+}
@@ -0,0 +1,18 @@
+use bitcoin::OutPoint;
+use ic_cdk::update;
+use std::str::FromStr;
+
+use crate::{auth_guard, coin_control::freeze_output};
+
+/// Freezes one of the caller's outputs, given as `txid:vout`, so that it is not spent until it
+/// is unfrozen, neither by coin selection nor as an explicitly chosen input. A principal can
+/// freeze up to 1,000 outputs.
+#[update]
+pub fn freeze_utxo(outpoint: String) -> Result<(), String> {
+    // Calls to freeze_utxo need to be authenticated
+    auth_guard()?;
+
+    let outpoint =
+        OutPoint::from_str(&outpoint).map_err(|e| format!("Invalid outpoint: {:?}", e))?;
+    freeze_output(ic_cdk::api::msg_caller(), outpoint)
+}
@@ -0,0 +1,9 @@
+use ic_cdk::query;
+
+use crate::{btc::FeeRateLimits, state::read_state};
+
+/// Returns the bounds on the fee rate of every transaction, in satoshi per virtual byte.
+#[query]
+pub fn get_fee_rate_limits() -> FeeRateLimits {
+    read_state(|state| state.fee_rate_limits)
+}
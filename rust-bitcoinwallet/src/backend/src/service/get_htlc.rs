@@ -0,0 +1,47 @@
+use bitcoin::taproot::LeafVersion;
+use candid::Principal;
+use ic_cdk::update;
+
+use crate::{htlc::CLAIM_LEAF, state::read_state, BTC_CONTEXT};
+
+/// Description of an HTLC, including the address it receives funds at and what the
+/// counterparty needs to claim them.
+#[derive(candid::CandidType, candid::Deserialize)]
+pub struct HtlcInfo {
+    pub owner: Principal,
+    pub counterparty_key: Vec<u8>,
+    pub payment_hash: Vec<u8>,
+    pub timeout: u32,
+    pub address: String,
+    /// The claim leaf script. The counterparty spends through it with the witness
+    /// `<signature> <preimage> <claim_script> <claim_control_block>`.
+    pub claim_script: Vec<u8>,
+    pub claim_control_block: Vec<u8>,
+}
+
+/// Returns the HTLC with the given id together with its address.
+#[update]
+pub async fn get_htlc(id: u64) -> Result<HtlcInfo, String> {
+    let htlc = read_state(|state| state.htlcs.get(&id).cloned())
+        .ok_or_else(|| format!("No HTLC with id {}", id))?;
+
+    // The Bitcoin context contains information about the currently selected Bitcoin network and the Bitcoin canister.
+    let ctx = BTC_CONTEXT.with(|ctx| ctx.get());
+
+    let compiled = htlc.policy_account().compile(&ctx).await?;
+    let claim_script = compiled.leaf(CLAIM_LEAF)?.script.clone();
+    let claim_control_block = compiled
+        .spend_info
+        .control_block(&(claim_script.clone(), LeafVersion::TapScript))
+        .expect("leaf is part of the script tree");
+
+    Ok(HtlcInfo {
+        owner: htlc.owner,
+        counterparty_key: htlc.counterparty_key,
+        payment_hash: htlc.payment_hash,
+        timeout: htlc.timeout,
+        address: compiled.address.to_string(),
+        claim_script: claim_script.into_bytes(),
+        claim_control_block: claim_control_block.serialize(),
+    })
+}
@@ -0,0 +1,37 @@
+use candid::Principal;
+use ic_cdk::update;
+
+use crate::{
+    account::{Account, AddressType, DEFAULT_ACCOUNT},
+    state::read_state,
+    BTC_CONTEXT,
+};
+
+/// Description of an inheritance plan, including the address of the inheritance account.
+#[derive(candid::CandidType, candid::Deserialize)]
+pub struct InheritanceInfo {
+    pub heir: Principal,
+    pub inactivity_blocks: u16,
+    pub address: String,
+}
+
+/// Returns the inheritance plan of the caller or of a specified principal.
+#[update]
+pub async fn get_inheritance(owner: Option<Principal>) -> Result<InheritanceInfo, String> {
+    // If no principal is specified in call, use caller principal
+    let owner = owner.unwrap_or_else(ic_cdk::api::msg_caller);
+
+    let plan = read_state(|state| state.inheritance_plans.get(&owner).cloned())
+        .ok_or("No inheritance plan set up")?;
+
+    // The Bitcoin context contains information about the currently selected Bitcoin network and the Bitcoin canister.
+    let ctx = BTC_CONTEXT.with(|ctx| ctx.get());
+
+    let account = Account::derive(&ctx, owner, DEFAULT_ACCOUNT, AddressType::Inheritance).await?;
+
+    Ok(InheritanceInfo {
+        heir: plan.heir,
+        inactivity_blocks: plan.inactivity_blocks,
+        address: account.address.to_string(),
+    })
+}
@@ -0,0 +1,9 @@
+use ic_cdk::query;
+
+use crate::{notary::Notarization, state::read_state};
+
+/// Returns the transactions that notarized `data`, oldest first.
+#[query]
+pub fn get_notarizations(data: Vec<u8>) -> Vec<Notarization> {
+    read_state(|state| state.notarizations.get(&data).cloned().unwrap_or_default())
+}
@@ -0,0 +1,40 @@
+use candid::Principal;
+use ic_cdk::update;
+
+use crate::{policy::Policy, state::read_state, BTC_CONTEXT};
+
+/// Description of a policy account, including the address it receives funds at.
+#[derive(candid::CandidType, candid::Deserialize)]
+pub struct PolicyAccountInfo {
+    pub owner: Principal,
+    pub key_path_owner: Option<Principal>,
+    pub policy: Policy,
+    pub address: String,
+    /// The compiled leaf scripts in human-readable form. The position of a leaf in
+    /// this list is the index used to select it when spending.
+    pub leaves: Vec<String>,
+}
+
+/// Returns the policy account with the given id together with its address and leaf scripts.
+#[update]
+pub async fn get_policy_account(id: u64) -> Result<PolicyAccountInfo, String> {
+    let account = read_state(|state| state.policy_accounts.get(&id).cloned())
+        .ok_or_else(|| format!("No policy account with id {}", id))?;
+
+    // The Bitcoin context contains information about the currently selected Bitcoin network and the Bitcoin canister.
+    let ctx = BTC_CONTEXT.with(|ctx| ctx.get());
+
+    let compiled = account.compile(&ctx).await?;
+
+    Ok(PolicyAccountInfo {
+        owner: account.owner,
+        key_path_owner: account.key_path_owner,
+        policy: account.policy,
+        address: compiled.address.to_string(),
+        leaves: compiled
+            .leaves
+            .iter()
+            .map(|leaf| leaf.script.to_asm_string())
+            .collect(),
+    })
+}
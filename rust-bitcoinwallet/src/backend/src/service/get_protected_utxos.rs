@@ -0,0 +1,12 @@
+use ic_cdk::query;
+
+use crate::protection::protected_outputs;
+
+/// Returns the caller's protected outputs as `txid:vout`, including the ones protected
+/// automatically. Outputs that have been spent in the meantime may still be listed.
+#[query]
+pub fn get_protected_utxos() -> Vec<String> {
+    protected_outputs(ic_cdk::api::msg_caller())
+        .into_iter()
+        .collect()
+}
@@ -0,0 +1,22 @@
+use ic_cdk::query;
+
+use crate::runes::{rune_balances, RuneId};
+
+/// The amount of a rune held by a principal.
+#[derive(candid::CandidType, candid::Deserialize)]
+pub struct RuneBalance {
+    pub rune_id: RuneId,
+    pub amount: u128,
+}
+
+/// Returns the caller's balance of each rune the smart contract knows the caller holds.
+///
+/// Only runes that were etched, minted or transferred through the smart contract are
+/// counted; runes received from elsewhere are not.
+#[query]
+pub fn get_rune_balances() -> Vec<RuneBalance> {
+    rune_balances(ic_cdk::api::msg_caller())
+        .into_iter()
+        .map(|(rune_id, amount)| RuneBalance { rune_id, amount })
+        .collect()
+}
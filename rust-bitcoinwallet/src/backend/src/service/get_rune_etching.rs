@@ -0,0 +1,10 @@
+use ic_cdk::query;
+
+use crate::{runes::RuneEtching, state::read_state};
+
+/// Returns the rune etching with the given id.
+#[query]
+pub fn get_rune_etching(id: u64) -> Result<RuneEtching, String> {
+    read_state(|state| state.rune_etchings.get(&id).cloned())
+        .ok_or_else(|| format!("No rune etching with id {}", id))
+}
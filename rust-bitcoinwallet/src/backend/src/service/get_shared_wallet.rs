@@ -0,0 +1,32 @@
+use candid::Principal;
+use ic_cdk::update;
+
+use crate::{state::read_state, BTC_CONTEXT};
+
+/// Description of a shared wallet, including the address it receives funds at.
+#[derive(candid::CandidType, candid::Deserialize)]
+pub struct SharedWalletInfo {
+    pub creator: Principal,
+    pub co_owners: Vec<Principal>,
+    pub threshold: u32,
+    pub address: String,
+}
+
+/// Returns the shared wallet with the given id together with its address.
+#[update]
+pub async fn get_shared_wallet(id: u64) -> Result<SharedWalletInfo, String> {
+    let wallet = read_state(|state| state.shared_wallets.get(&id).cloned())
+        .ok_or_else(|| format!("No shared wallet with id {}", id))?;
+
+    // The Bitcoin context contains information about the currently selected Bitcoin network and the Bitcoin canister.
+    let ctx = BTC_CONTEXT.with(|ctx| ctx.get());
+
+    let compiled = wallet.policy_account().compile(&ctx).await?;
+
+    Ok(SharedWalletInfo {
+        creator: wallet.creator,
+        co_owners: wallet.co_owners,
+        threshold: wallet.threshold,
+        address: compiled.address.to_string(),
+    })
+}
@@ -0,0 +1,19 @@
+use ic_cdk::query;
+
+use crate::{multisig::SpendProposal, state::read_state};
+
+/// Returns all spend proposals of the shared wallet with the given id, oldest first.
+#[query]
+pub fn get_spend_proposals(wallet_id: u64) -> Result<Vec<SpendProposal>, String> {
+    read_state(|state| {
+        if !state.shared_wallets.contains_key(&wallet_id) {
+            return Err(format!("No shared wallet with id {}", wallet_id));
+        }
+        Ok(state
+            .spend_proposals
+            .values()
+            .filter(|proposal| proposal.wallet_id == wallet_id)
+            .cloned()
+            .collect())
+    })
+}
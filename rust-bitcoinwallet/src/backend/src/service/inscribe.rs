@@ -0,0 +1,151 @@
+use bitcoin::{consensus::serialize, OutPoint, Transaction, Txid};
+use candid::Principal;
+use ic_cdk::{
+    bitcoin_canister::{bitcoin_send_transaction, SendTransactionRequest},
+    update,
+};
+
+use crate::{
+    account::{Account, AddressType, DEFAULT_ACCOUNT},
+    auth_guard,
+    btc::{dust_limit, get_fee_per_byte, PrimaryOutput},
+    ordinals::{self, Envelope, PendingReveal},
+    protection::protect_output,
+    state::mutate_state,
+    BitcoinContext, BTC_CONTEXT,
+};
+
+/// The transactions that created an inscription.
+#[derive(candid::CandidType, candid::Deserialize)]
+pub struct InscriptionInfo {
+    /// The inscription id, `<reveal txid>i0`.
+    pub id: String,
+    pub commit_txid: String,
+    pub reveal_txid: String,
+}
+
+/// Inscribes `body` with the given MIME content type and returns the resulting transactions.
+///
+/// The commit transaction is funded from the caller's default P2TR account, and the reveal
+/// transaction sends the inscription to that account's address with the smallest postage
+/// that is not dust. The fees of both transactions are computed before anything is signed, and the
+/// commit transaction is broadcast before the reveal transaction. Should broadcasting the
+/// reveal transaction fail, `retry_inscription_reveal` broadcasts it again. The inscribed
+/// output is protected from ordinary coin selection; `send_utxo` moves it on.
+#[update]
+pub async fn inscribe(content_type: String, body: Vec<u8>) -> Result<InscriptionInfo, String> {
+    // Calls to inscribe need to be authenticated
+    auth_guard()?;
+
+    let envelope = Envelope { content_type, body };
+    envelope.validate()?;
+
+    let ctx = BTC_CONTEXT.with(|ctx| ctx.get());
+    let inscriber = ic_cdk::api::msg_caller();
+
+    let mut account = Account::derive(&ctx, inscriber, DEFAULT_ACCOUNT, AddressType::P2tr).await?;
+    account.load_receive_addresses(&ctx).await;
+
+    let commit = ordinals::commit_address(&ctx, inscriber, &envelope.pushes()).await?;
+
+    // The commit output has to cover the postage and the fee of the reveal transaction.
+    let fee_per_byte = get_fee_per_byte(&ctx).await;
+    let reveal_fee =
+        ordinals::reveal_fee(&ctx, &commit, &[], &account.address, fee_per_byte).await?;
+    let commit_value = dust_limit(&account.address) + reveal_fee;
+
+    // Get all UTXOs of the account's main and receive addresses, except protected and frozen
+    // ones.
+    let own_utxos = account.get_spendable_utxos(&ctx).await?;
+
+    if own_utxos.is_empty() {
+        return Err("No UTXOs available for spending".to_string());
+    }
+
+    let (commit_transaction, prevouts) = account
+        .build_transaction(
+            &ctx,
+            &own_utxos,
+            &[PrimaryOutput::Address(commit.address.clone(), commit_value)],
+            fee_per_byte,
+        )
+        .await?;
+    let commit_transaction = account
+        .sign_transaction(&ctx, commit_transaction, prevouts.as_slice())
+        .await;
+
+    let (reveal_transaction, prevouts) = ordinals::build_reveal_transaction(
+        &ctx,
+        &commit,
+        commit_transaction.compute_txid(),
+        commit_value,
+        &[],
+        &account.address,
+        fee_per_byte,
+    )
+    .await?;
+    let reveal_transaction =
+        ordinals::sign_reveal_transaction(&ctx, &commit, reveal_transaction, prevouts.as_slice())
+            .await;
+
+    // Keep the reveal transaction until it is accepted, so that the commit output can still be
+    // spent if broadcasting it fails once the commit transaction is out.
+    let commit_txid = commit_transaction.compute_txid();
+    mutate_state(|state| {
+        state.pending_reveals.insert(
+            commit_txid.to_string(),
+            PendingReveal {
+                owner: inscriber,
+                reveal_transaction: serialize(&reveal_transaction),
+            },
+        )
+    });
+
+    // The reveal transaction spends the commit output, so the commit transaction goes first.
+    if let Err(e) = bitcoin_send_transaction(&SendTransactionRequest {
+        network: ctx.network,
+        transaction: serialize(&commit_transaction),
+    })
+    .await
+    {
+        mutate_state(|state| state.pending_reveals.remove(&commit_txid.to_string()));
+        return Err(format!("Failed to send transaction: {:?}", e));
+    }
+
+    send_reveal_transaction(&ctx, inscriber, commit_txid, &reveal_transaction).await
+}
+
+/// Broadcasts the reveal transaction of the inscription committed to in `commit_txid`, and
+/// protects the inscribed output once it is accepted. Otherwise, the reveal transaction is kept
+/// for `retry_inscription_reveal`.
+pub(crate) async fn send_reveal_transaction(
+    ctx: &BitcoinContext,
+    inscriber: Principal,
+    commit_txid: Txid,
+    reveal_transaction: &Transaction,
+) -> Result<InscriptionInfo, String> {
+    bitcoin_send_transaction(&SendTransactionRequest {
+        network: ctx.network,
+        transaction: serialize(reveal_transaction),
+    })
+    .await
+    .map_err(|e| {
+        format!(
+            "Failed to send reveal transaction: {:?}; the commit transaction {} was sent, \
+             retry with retry_inscription_reveal",
+            e, commit_txid
+        )
+    })?;
+    mutate_state(|state| state.pending_reveals.remove(&commit_txid.to_string()));
+
+    // The inscription sits on the first sat of the reveal transaction's only output.
+    let reveal_txid = reveal_transaction.compute_txid();
+    protect_output(inscriber, OutPoint::new(reveal_txid, 0));
+
+    let reveal_txid = reveal_txid.to_string();
+    Ok(InscriptionInfo {
+        id: format!("{}i0", reveal_txid),
+        commit_txid: commit_txid.to_string(),
+        reveal_txid,
+    })
+}
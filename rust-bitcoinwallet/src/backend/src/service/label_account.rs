@@ -0,0 +1,34 @@
+use ic_cdk::update;
+
+use crate::{
+    account::{ensure_account_exists, AccountId, MAX_LABEL_LENGTH},
+    auth_guard,
+    state::mutate_state,
+};
+
+/// Sets the label of one of the caller's accounts, including the default account.
+#[update]
+pub fn label_account(account: AccountId, label: String) -> Result<(), String> {
+    // Calls to label_account need to be authenticated
+    auth_guard()?;
+
+    if label.len() > MAX_LABEL_LENGTH {
+        return Err(format!(
+            "Label must be at most {} bytes long",
+            MAX_LABEL_LENGTH
+        ));
+    }
+
+    let caller = ic_cdk::api::msg_caller();
+    ensure_account_exists(caller, account)?;
+
+    mutate_state(|state| {
+        state
+            .accounts
+            .entry(caller)
+            .or_default()
+            .insert(account, label)
+    });
+
+    Ok(())
+}
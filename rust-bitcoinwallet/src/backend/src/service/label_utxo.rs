@@ -0,0 +1,24 @@
+use bitcoin::OutPoint;
+use ic_cdk::update;
+use std::str::FromStr;
+
+use crate::{account::MAX_LABEL_LENGTH, auth_guard, coin_control::label_output};
+
+/// Sets the label of one of the caller's outputs, given as `txid:vout`. An empty label removes
+/// it. A principal can label up to 1,000 outputs.
+#[update]
+pub fn label_utxo(outpoint: String, label: String) -> Result<(), String> {
+    // Calls to label_utxo need to be authenticated
+    auth_guard()?;
+
+    if label.len() > MAX_LABEL_LENGTH {
+        return Err(format!(
+            "Label must be at most {} bytes long",
+            MAX_LABEL_LENGTH
+        ));
+    }
+
+    let outpoint =
+        OutPoint::from_str(&outpoint).map_err(|e| format!("Invalid outpoint: {:?}", e))?;
+    label_output(ic_cdk::api::msg_caller(), outpoint, label)
+}
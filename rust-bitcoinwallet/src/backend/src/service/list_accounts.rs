@@ -0,0 +1,28 @@
+use ic_cdk::query;
+
+use crate::{
+    account::{AccountId, DEFAULT_ACCOUNT},
+    state::read_state,
+};
+
+/// An account of a principal.
+#[derive(candid::CandidType, candid::Deserialize)]
+pub struct AccountInfo {
+    pub id: AccountId,
+    pub label: String,
+}
+
+/// Returns the caller's accounts ordered by id, starting with the default account.
+#[query]
+pub fn list_accounts() -> Vec<AccountInfo> {
+    let caller = ic_cdk::api::msg_caller();
+
+    read_state(|state| {
+        let mut accounts = state.accounts.get(&caller).cloned().unwrap_or_default();
+        accounts.entry(DEFAULT_ACCOUNT).or_default();
+        accounts
+            .into_iter()
+            .map(|(id, label)| AccountInfo { id, label })
+            .collect()
+    })
+}
@@ -0,0 +1,69 @@
+use ic_cdk::update;
+
+use crate::{
+    account::{Account, AccountId, AddressType, DEFAULT_ACCOUNT},
+    auth_guard,
+    btc::outpoint,
+    coin_control::{is_frozen, output_label},
+    protection::is_protected,
+    BTC_CONTEXT,
+};
+
+/// An unspent output of an account, with the notes its owner attached to it.
+#[derive(candid::CandidType, candid::Deserialize)]
+pub struct UtxoInfo {
+    /// The outpoint, `txid:vout`.
+    pub outpoint: String,
+    pub value: u64,
+    /// The height of the block that confirmed the output.
+    pub height: u32,
+    pub confirmations: u32,
+    pub label: Option<String>,
+    /// Whether coin selection skips the output because its owner froze it.
+    pub frozen: bool,
+    /// Whether coin selection skips the output because it carries an inscription or runes.
+    pub protected: bool,
+}
+
+/// Lists the unspent outputs of the caller's account, across its main and receive addresses.
+///
+/// The account and address type default to the default account and P2TR.
+#[update]
+pub async fn list_utxos(
+    address_type: Option<AddressType>,
+    account: Option<AccountId>,
+) -> Result<Vec<UtxoInfo>, String> {
+    // Calls to list_utxos need to be authenticated
+    auth_guard()?;
+
+    let ctx = BTC_CONTEXT.with(|ctx| ctx.get());
+    let owner = ic_cdk::api::msg_caller();
+
+    let mut account = Account::derive(
+        &ctx,
+        owner,
+        account.unwrap_or(DEFAULT_ACCOUNT),
+        address_type.unwrap_or_default(),
+    )
+    .await?;
+    account.load_receive_addresses(&ctx).await;
+
+    let utxos = account.get_utxos(&ctx).await?;
+    let tip_height = account.tip_height();
+
+    Ok(utxos
+        .iter()
+        .map(|utxo| {
+            let outpoint = outpoint(utxo);
+            UtxoInfo {
+                outpoint: outpoint.to_string(),
+                value: utxo.value,
+                height: utxo.height,
+                confirmations: (tip_height + 1).saturating_sub(utxo.height),
+                label: output_label(owner, &outpoint),
+                frozen: is_frozen(owner, &outpoint),
+                protected: is_protected(owner, &outpoint),
+            }
+        })
+        .collect())
+}
@@ -0,0 +1,102 @@
+use bitcoin::{consensus::serialize, OutPoint};
+use ic_cdk::{
+    bitcoin_canister::{bitcoin_send_transaction, SendTransactionRequest},
+    update,
+};
+
+use crate::{
+    account::{Account, AddressType, DEFAULT_ACCOUNT},
+    auth_guard,
+    btc::{dust_limit, get_fee_per_byte, PrimaryOutput},
+    protection::protect_output,
+    runes::{record_rune_output, RuneBalances, RuneId, Runestone},
+    state::read_state,
+    BTC_CONTEXT,
+};
+
+/// Mints the rune with the given id into the caller's default P2TR account.
+///
+/// The transaction carries a runestone with the mint, followed by a dust-sized output to the
+/// caller's P2TR address that receives the minted runes. Whether the mint succeeds depends on
+/// the terms of the rune, which the smart contract does not check: minting a closed rune only
+/// costs the fee.
+///
+/// `amount` is the amount of runes each mint creates according to the terms of the rune. It
+/// defaults to the terms of a confirmed etching made through the smart contract. If it is
+/// known, the minted runes count towards the caller's rune balance and can be moved with
+/// `transfer_rune`.
+#[update]
+pub async fn mint_rune(rune_id: RuneId, amount: Option<u128>) -> Result<String, String> {
+    // Calls to mint_rune need to be authenticated
+    auth_guard()?;
+
+    let amount = amount.or_else(|| {
+        read_state(|state| {
+            state
+                .rune_etchings
+                .values()
+                .find(|rune_etching| rune_etching.rune_id == Some(rune_id))
+                .and_then(|rune_etching| rune_etching.etching.terms.as_ref()?.amount)
+        })
+    });
+
+    let runestone = Runestone {
+        mint: Some(rune_id),
+        ..Default::default()
+    }
+    .encipher()?;
+
+    let ctx = BTC_CONTEXT.with(|ctx| ctx.get());
+
+    let owner = ic_cdk::api::msg_caller();
+
+    let mut account = Account::derive(&ctx, owner, DEFAULT_ACCOUNT, AddressType::P2tr).await?;
+    account.load_receive_addresses(&ctx).await;
+
+    // Get all UTXOs of the account's main and receive addresses, except protected and frozen
+    // ones.
+    let own_utxos = account.get_spendable_utxos(&ctx).await?;
+
+    if own_utxos.is_empty() {
+        return Err("No UTXOs available for spending".to_string());
+    }
+
+    // Minted runes go to the first output that is not an OP_RETURN output.
+    let fee_per_byte = get_fee_per_byte(&ctx).await;
+    let (transaction, prevouts) = account
+        .build_transaction(
+            &ctx,
+            &own_utxos,
+            &[
+                PrimaryOutput::OpReturn(runestone),
+                PrimaryOutput::Address(account.address.clone(), dust_limit(&account.address)),
+            ],
+            fee_per_byte,
+        )
+        .await?;
+    let signed_transaction = account
+        .sign_transaction(&ctx, transaction, prevouts.as_slice())
+        .await;
+
+    // Send the transaction to the Bitcoin network.
+    bitcoin_send_transaction(&SendTransactionRequest {
+        network: ctx.network,
+        transaction: serialize(&signed_transaction),
+    })
+    .await
+    .map_err(|e| format!("Failed to send transaction: {:?}", e))?;
+
+    // Without the amount, the minted runes are unknown, but the output still gets protected.
+    let txid = signed_transaction.compute_txid();
+    match amount {
+        Some(amount) => record_rune_output(
+            owner,
+            OutPoint::new(txid, 1),
+            RuneBalances::from([(rune_id, amount)]),
+        ),
+        None => protect_output(owner, OutPoint::new(txid, 1)),
+    }
+
+    // Return the transaction ID.
+    Ok(txid.to_string())
+}
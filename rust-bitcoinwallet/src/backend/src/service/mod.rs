@@ -1,3 +1,50 @@
+pub mod approve_spend;
+pub mod bump_fee;
+pub mod cancel_transaction;
+pub mod claim_inheritance;
+pub mod confirm_rune_etching;
+pub mod cpfp;
+pub mod create_account;
+pub mod create_htlc;
+pub mod create_policy_account;
+pub mod create_shared_wallet;
+pub mod etch_rune;
+pub mod freeze_utxo;
 pub mod get_address;
 pub mod get_balance;
+pub mod get_fee_rate_limits;
+pub mod get_htlc;
+pub mod get_inheritance;
+pub mod get_notarizations;
+pub mod get_policy_account;
+pub mod get_protected_utxos;
+pub mod get_rune_balances;
+pub mod get_rune_etching;
+pub mod get_shared_wallet;
+pub mod get_spend_proposals;
+pub mod inscribe;
+pub mod label_account;
+pub mod label_utxo;
+pub mod list_accounts;
+pub mod list_utxos;
+pub mod mint_rune;
+pub mod new_receive_address;
+pub mod notarize;
+pub mod preview_send;
+pub mod propose_spend;
+pub mod protect_utxo;
+pub mod recover_rune_commit;
+pub mod refund_htlc;
+pub mod retry_inscription_reveal;
+pub mod reveal_rune_etching;
 pub mod send_btc;
+pub mod send_many;
+pub mod send_utxo;
+pub mod set_coin_selection;
+pub mod set_fee_rate_limits;
+pub mod set_inheritance;
+pub mod spend_policy_account;
+pub mod sweep;
+pub mod transfer_rune;
+pub mod unfreeze_utxo;
+pub mod unprotect_utxo;
@@ -0,0 +1,34 @@
+use ic_cdk::update;
+
+use crate::{
+    account::{Account, AccountId, AddressType, DEFAULT_ACCOUNT},
+    auth_guard, BTC_CONTEXT,
+};
+
+/// Issues a fresh receive address of the caller's account, so that payments to the caller
+/// cannot be linked to each other on-chain.
+///
+/// Receive addresses are derived at consecutive indices below the account's derivation path
+/// and count towards its balance and spendable outputs. At most `GAP_LIMIT` addresses in a
+/// row may be issued without any of them receiving funds. The address returned by
+/// `get_address` stays the account's main address and receives all change.
+#[update]
+pub async fn new_receive_address(
+    address_type: Option<AddressType>,
+    account: Option<AccountId>,
+) -> Result<String, String> {
+    // Calls to new_receive_address need to be authenticated
+    auth_guard()?;
+
+    let ctx = BTC_CONTEXT.with(|ctx| ctx.get());
+
+    let mut account = Account::derive(
+        &ctx,
+        ic_cdk::api::msg_caller(),
+        account.unwrap_or(DEFAULT_ACCOUNT),
+        address_type.unwrap_or_default(),
+    )
+    .await?;
+
+    Ok(account.issue_receive_address(&ctx).await?.to_string())
+}
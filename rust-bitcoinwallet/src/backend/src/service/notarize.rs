@@ -0,0 +1,81 @@
+use bitcoin::consensus::serialize;
+use ic_cdk::{
+    bitcoin_canister::{bitcoin_send_transaction, SendTransactionRequest},
+    update,
+};
+
+use crate::{
+    account::{Account, AddressType, DEFAULT_ACCOUNT},
+    auth_guard,
+    btc::{get_fee_per_byte, PrimaryOutput},
+    notary::{op_return_script, Notarization},
+    state::mutate_state,
+    BTC_CONTEXT,
+};
+
+/// Timestamps `data` on Bitcoin and returns the id of the transaction embedding it.
+///
+/// The data, at most 80 bytes such as the hash of a document, is placed in an OP_RETURN
+/// output of a transaction funded from the caller's default P2TR account. The transaction is
+/// recorded so that it can be looked up with `get_notarizations`.
+#[update]
+pub async fn notarize(data: Vec<u8>) -> Result<String, String> {
+    // Calls to notarize need to be authenticated
+    auth_guard()?;
+
+    let script = op_return_script(&data)?;
+
+    let ctx = BTC_CONTEXT.with(|ctx| ctx.get());
+    let notary = ic_cdk::api::msg_caller();
+
+    let mut account = Account::derive(&ctx, notary, DEFAULT_ACCOUNT, AddressType::P2tr).await?;
+    account.load_receive_addresses(&ctx).await;
+
+    // Get all UTXOs of the account's main and receive addresses, except protected and frozen
+    // ones.
+    let own_utxos = account.get_spendable_utxos(&ctx).await?;
+
+    if own_utxos.is_empty() {
+        return Err("No UTXOs available for spending".to_string());
+    }
+
+    // Build the transaction. The OP_RETURN output carries no value, so the inputs only need
+    // to cover the fee.
+    let fee_per_byte = get_fee_per_byte(&ctx).await;
+    let (transaction, prevouts) = account
+        .build_transaction(
+            &ctx,
+            &own_utxos,
+            &[PrimaryOutput::OpReturn(script)],
+            fee_per_byte,
+        )
+        .await?;
+
+    let signed_transaction = account
+        .sign_transaction(&ctx, transaction, prevouts.as_slice())
+        .await;
+
+    // Send the transaction to the Bitcoin network.
+    bitcoin_send_transaction(&SendTransactionRequest {
+        network: ctx.network,
+        transaction: serialize(&signed_transaction),
+    })
+    .await
+    .map_err(|e| format!("Failed to send transaction: {:?}", e))?;
+
+    let txid = signed_transaction.compute_txid().to_string();
+    mutate_state(|state| {
+        state
+            .notarizations
+            .entry(data)
+            .or_default()
+            .push(Notarization {
+                txid: txid.clone(),
+                notary,
+                timestamp: ic_cdk::api::time(),
+            })
+    });
+
+    // Return the transaction ID.
+    Ok(txid)
+}
@@ -0,0 +1,106 @@
+use bitcoin::Address;
+use ic_cdk::update;
+
+use crate::{
+    account::{AccountId, AddressType},
+    auth_guard,
+    btc::transaction_fee,
+    service::send_btc::{build_send, SendOptions},
+    BTC_CONTEXT,
+};
+
+/// An input of a previewed transaction.
+#[derive(candid::CandidType, candid::Deserialize)]
+pub struct PreviewInput {
+    /// The outpoint spent, `txid:vout`.
+    pub outpoint: String,
+    pub value: u64,
+}
+
+/// An output of a previewed transaction.
+#[derive(candid::CandidType, candid::Deserialize)]
+pub struct PreviewOutput {
+    /// The receiving address, or none for an OP_RETURN output.
+    pub address: Option<String>,
+    pub value: u64,
+}
+
+/// The transaction `send_btc` would send, before it is signed.
+#[derive(candid::CandidType, candid::Deserialize)]
+pub struct SendPreview {
+    pub inputs: Vec<PreviewInput>,
+    /// All outputs, including the change output.
+    pub outputs: Vec<PreviewOutput>,
+    /// The value of the change output, if there is one.
+    pub change: Option<u64>,
+    /// The virtual size of the signed transaction.
+    pub vsize: u64,
+    /// The fee in satoshi.
+    pub fee: u64,
+    /// The fee divided by the virtual size, in satoshi per virtual byte.
+    pub fee_rate: f64,
+}
+
+/// Previews the transaction `send_btc` would send for the same arguments, without signing or
+/// sending anything.
+///
+/// It goes through the same validation, UTXO fetch, coin selection and fee computation. The
+/// actual transaction may still differ if the account's UTXOs or the network's fee rates
+/// change before `send_btc` is called.
+#[update]
+pub async fn preview_send(
+    destination_address: String,
+    amount_in_satoshi: u64,
+    address_type: Option<AddressType>,
+    account: Option<AccountId>,
+    options: Option<SendOptions>,
+) -> Result<SendPreview, String> {
+    // Calls to preview_send need to be authenticated
+    auth_guard()?;
+
+    let ctx = BTC_CONTEXT.with(|ctx| ctx.get());
+    let (account, transaction, prevouts) = build_send(
+        &ctx,
+        destination_address,
+        amount_in_satoshi,
+        address_type,
+        account,
+        options.unwrap_or_default(),
+    )
+    .await?;
+
+    let vsize = account.predicted_vsize(&transaction);
+    let fee = transaction_fee(&transaction, &prevouts);
+
+    // The payment is the first output, any change output comes after it.
+    let change = transaction
+        .output
+        .get(1)
+        .map(|change_output| change_output.value.to_sat());
+
+    Ok(SendPreview {
+        inputs: transaction
+            .input
+            .iter()
+            .zip(prevouts.iter())
+            .map(|(input, prevout)| PreviewInput {
+                outpoint: input.previous_output.to_string(),
+                value: prevout.value.to_sat(),
+            })
+            .collect(),
+        outputs: transaction
+            .output
+            .iter()
+            .map(|output| PreviewOutput {
+                address: Address::from_script(&output.script_pubkey, ctx.bitcoin_network)
+                    .ok()
+                    .map(|address| address.to_string()),
+                value: output.value.to_sat(),
+            })
+            .collect(),
+        change,
+        vsize,
+        fee,
+        fee_rate: fee as f64 / vsize as f64,
+    })
+}
@@ -0,0 +1,65 @@
+use bitcoin::Address;
+use ic_cdk::update;
+use std::str::FromStr;
+
+use crate::{
+    auth_guard,
+    multisig::{self, ProposalStatus, SpendProposal},
+    state::{mutate_state, read_state},
+    BTC_CONTEXT,
+};
+
+/// Proposes sending Bitcoin from a shared wallet to the specified destination.
+///
+/// The proposal counts as approved by the caller, who must be a co-owner of the wallet. If
+/// that already meets the wallet's threshold, the transaction is signed and broadcast right
+/// away; otherwise it waits for further approvals through `approve_spend`.
+#[update]
+pub async fn propose_spend(
+    wallet_id: u64,
+    destination_address: String,
+    amount_in_satoshi: u64,
+) -> Result<SpendProposal, String> {
+    // Calls to propose_spend need to be authenticated
+    auth_guard()?;
+
+    let ctx = BTC_CONTEXT.with(|ctx| ctx.get());
+
+    if amount_in_satoshi == 0 {
+        return Err("Amount must be greater than 0".to_string());
+    }
+
+    // Parse and validate the destination address. The address type needs to be
+    // valid for the Bitcoin network we are on.
+    Address::from_str(&destination_address)
+        .map_err(|e| format!("Invalid destination address: {}", e))?
+        .require_network(ctx.bitcoin_network)
+        .map_err(|e| format!("Address not valid for network: {}", e))?;
+
+    let caller = ic_cdk::api::msg_caller();
+
+    let wallet = read_state(|state| state.shared_wallets.get(&wallet_id).cloned())
+        .ok_or_else(|| format!("No shared wallet with id {}", wallet_id))?;
+    if !wallet.co_owners.contains(&caller) {
+        return Err("Only co-owners can propose a spend".to_string());
+    }
+
+    let proposal_id = mutate_state(|state| {
+        let id = state.next_spend_proposal_id;
+        state.next_spend_proposal_id += 1;
+        state.spend_proposals.insert(
+            id,
+            SpendProposal {
+                id,
+                wallet_id,
+                destination_address,
+                amount_in_satoshi,
+                approvals: vec![],
+                status: ProposalStatus::Pending,
+            },
+        );
+        id
+    });
+
+    multisig::approve(&ctx, proposal_id, caller).await
+}
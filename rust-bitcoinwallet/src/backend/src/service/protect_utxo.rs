@@ -0,0 +1,21 @@
+use bitcoin::OutPoint;
+use ic_cdk::update;
+use std::str::FromStr;
+
+use crate::{auth_guard, protection::request_protection};
+
+/// Protects one of the caller's outputs, given as `txid:vout`, from ordinary coin selection.
+///
+/// Protected outputs are never spent to fund payments or fees, only by `send_utxo`. Use this
+/// for outputs holding inscriptions or other collectibles that were not created through the
+/// smart contract, which protects those automatically. A principal can protect up to 1,000
+/// outputs.
+#[update]
+pub fn protect_utxo(outpoint: String) -> Result<(), String> {
+    // Calls to protect_utxo need to be authenticated
+    auth_guard()?;
+
+    let outpoint =
+        OutPoint::from_str(&outpoint).map_err(|e| format!("Invalid outpoint: {:?}", e))?;
+    request_protection(ic_cdk::api::msg_caller(), outpoint)
+}
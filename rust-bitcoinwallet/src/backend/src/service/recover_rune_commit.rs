@@ -0,0 +1,100 @@
+use bitcoin::{consensus::serialize, Txid};
+use ic_cdk::{
+    bitcoin_canister::{
+        bitcoin_get_utxos, bitcoin_send_transaction, GetUtxosRequest, SendTransactionRequest,
+    },
+    update,
+};
+use std::str::FromStr;
+
+use crate::{
+    account::{Account, AddressType, DEFAULT_ACCOUNT},
+    auth_guard,
+    btc::{get_fee_per_byte, outpoint},
+    ordinals,
+    state::{mutate_state, read_state},
+    BTC_CONTEXT,
+};
+
+/// Gives up a rune etching started with `etch_rune` and sends the commit output back to the
+/// caller's P2TR address.
+///
+/// This is the way out when the etching transaction cannot be built or does not get mined,
+/// e.g. because fee rates rose far above the reveal fee fixed by `etch_rune`. The commit
+/// output is spent through the key path at the current fee rate, which reveals nothing about
+/// the rune. Only the principal that started the etching can call this, and only before the
+/// etching is revealed. Returns the id of the recovery transaction.
+#[update]
+pub async fn recover_rune_commit(id: u64) -> Result<String, String> {
+    // Calls to recover_rune_commit need to be authenticated
+    auth_guard()?;
+
+    let ctx = BTC_CONTEXT.with(|ctx| ctx.get());
+    let owner = ic_cdk::api::msg_caller();
+
+    let rune_etching = read_state(|state| state.rune_etchings.get(&id).cloned())
+        .ok_or_else(|| format!("No rune etching with id {}", id))?;
+    if rune_etching.owner != owner {
+        return Err("Only the etcher can recover a rune commit".to_string());
+    }
+    if let Some(reveal_txid) = rune_etching.reveal_txid {
+        return Err(format!("Rune etching was revealed in {}", reveal_txid));
+    }
+    if let Some(recovery_txid) = rune_etching.recovery_txid {
+        return Err(format!("Commit output was recovered in {}", recovery_txid));
+    }
+
+    let commit =
+        ordinals::commit_address(&ctx, owner, &[rune_etching.etching.commitment()?]).await?;
+    let commit_txid = Txid::from_str(&rune_etching.commit_txid).unwrap();
+
+    // Find the commit output among the UTXOs of the commit address.
+    // Note that pagination may have to be used to get all UTXOs for the given address.
+    // For the sake of simplicity, it is assumed here that the `utxo` field in the response
+    // contains all UTXOs.
+    let utxos_response = bitcoin_get_utxos(&GetUtxosRequest {
+        address: commit.address.to_string(),
+        network: ctx.network,
+        filter: None,
+    })
+    .await
+    .map_err(|e| format!("Failed to get UTXOs: {:?}", e))?;
+    let commit_utxo = utxos_response
+        .utxos
+        .iter()
+        .find(|utxo| outpoint(utxo).txid == commit_txid)
+        .ok_or_else(|| format!("Commit transaction {} is not confirmed yet", commit_txid))?;
+
+    let account = Account::derive(&ctx, owner, DEFAULT_ACCOUNT, AddressType::P2tr).await?;
+
+    let fee_per_byte = get_fee_per_byte(&ctx).await;
+    let (transaction, prevouts) = ordinals::build_recovery_transaction(
+        &ctx,
+        &commit,
+        commit_txid,
+        commit_utxo.value,
+        &account.address,
+        fee_per_byte,
+    )
+    .await?;
+    let signed_transaction =
+        ordinals::sign_recovery_transaction(&ctx, &commit, transaction, prevouts.as_slice()).await;
+
+    // Send the transaction to the Bitcoin network.
+    bitcoin_send_transaction(&SendTransactionRequest {
+        network: ctx.network,
+        transaction: serialize(&signed_transaction),
+    })
+    .await
+    .map_err(|e| format!("Failed to send transaction: {:?}", e))?;
+
+    let recovery_txid = signed_transaction.compute_txid().to_string();
+    mutate_state(|state| {
+        if let Some(rune_etching) = state.rune_etchings.get_mut(&id) {
+            rune_etching.recovery_txid = Some(recovery_txid.clone());
+        }
+    });
+
+    // Return the transaction ID.
+    Ok(recovery_txid)
+}
@@ -0,0 +1,34 @@
+use bitcoin::Address;
+use ic_cdk::update;
+use std::str::FromStr;
+
+use crate::{auth_guard, htlc, state::read_state, BTC_CONTEXT};
+
+/// Sends all funds of an HTLC back to the specified destination after its timeout.
+///
+/// Only the owner of the HTLC can call this, and only once the block after the current tip
+/// is above the HTLC's timeout height.
+#[update]
+pub async fn refund_htlc(id: u64, destination_address: String) -> Result<String, String> {
+    // Calls to refund_htlc need to be authenticated
+    auth_guard()?;
+
+    let ctx = BTC_CONTEXT.with(|ctx| ctx.get());
+
+    // Parse and validate the destination address. The address type needs to be
+    // valid for the Bitcoin network we are on.
+    let dst_address = Address::from_str(&destination_address)
+        .map_err(|e| format!("Invalid destination address: {}", e))?
+        .require_network(ctx.bitcoin_network)
+        .map_err(|e| format!("Address not valid for network: {}", e))?;
+
+    let caller = ic_cdk::api::msg_caller();
+
+    let htlc = read_state(|state| state.htlcs.get(&id).cloned())
+        .ok_or_else(|| format!("No HTLC with id {}", id))?;
+    if htlc.owner != caller {
+        return Err("Only the owner can refund an HTLC".to_string());
+    }
+
+    htlc::refund(&ctx, &htlc, &dst_address).await
+}
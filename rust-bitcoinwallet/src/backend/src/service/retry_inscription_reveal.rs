@@ -0,0 +1,32 @@
+use bitcoin::{consensus::deserialize, Txid};
+use ic_cdk::update;
+use std::str::FromStr;
+
+use crate::{
+    auth_guard,
+    service::inscribe::{send_reveal_transaction, InscriptionInfo},
+    state::read_state,
+    BTC_CONTEXT,
+};
+
+/// Broadcasts the reveal transaction of one of the caller's inscriptions again, given the txid
+/// of its commit transaction, after `inscribe` sent the commit transaction but failed to send
+/// the reveal transaction.
+#[update]
+pub async fn retry_inscription_reveal(commit_txid: String) -> Result<InscriptionInfo, String> {
+    // Calls to retry_inscription_reveal need to be authenticated
+    auth_guard()?;
+
+    let ctx = BTC_CONTEXT.with(|ctx| ctx.get());
+    let inscriber = ic_cdk::api::msg_caller();
+
+    let commit_txid = Txid::from_str(&commit_txid).map_err(|e| format!("Invalid txid: {:?}", e))?;
+    let pending_reveal =
+        read_state(|state| state.pending_reveals.get(&commit_txid.to_string()).cloned())
+            .filter(|pending_reveal| pending_reveal.owner == inscriber)
+            .ok_or_else(|| format!("No pending reveal of the caller for commit {}", commit_txid))?;
+    let reveal_transaction =
+        deserialize(&pending_reveal.reveal_transaction).expect("pending reveals are valid");
+
+    send_reveal_transaction(&ctx, inscriber, commit_txid, &reveal_transaction).await
+}
@@ -0,0 +1,122 @@
+use bitcoin::{consensus::serialize, OutPoint, Txid};
+use ic_cdk::{
+    bitcoin_canister::{
+        bitcoin_get_utxos, bitcoin_send_transaction, GetUtxosRequest, SendTransactionRequest,
+    },
+    update,
+};
+use std::str::FromStr;
+
+use crate::{
+    account::{Account, AddressType, DEFAULT_ACCOUNT},
+    auth_guard,
+    btc::{dust_limit, outpoint, PrimaryOutput},
+    ordinals,
+    protection::protect_output,
+    runes::{Runestone, COMMIT_CONFIRMATIONS},
+    state::{mutate_state, read_state},
+    BTC_CONTEXT,
+};
+
+/// Broadcasts the etching transaction of a rune etching started with `etch_rune`.
+///
+/// Only the principal that started the etching can call this, and only once the commit
+/// transaction has six confirmations. The etching transaction spends the commit output, and
+/// sends the premine, if any, to the caller's P2TR address. It pays the reveal fee fixed by
+/// `etch_rune`; should that no longer get it mined, `recover_rune_commit` returns the commit
+/// output to the caller instead. Returns the id of the etching transaction; the rune id is
+/// the block height and index at which it gets mined.
+#[update]
+pub async fn reveal_rune_etching(id: u64) -> Result<String, String> {
+    // Calls to reveal_rune_etching need to be authenticated
+    auth_guard()?;
+
+    let ctx = BTC_CONTEXT.with(|ctx| ctx.get());
+    let owner = ic_cdk::api::msg_caller();
+
+    let rune_etching = read_state(|state| state.rune_etchings.get(&id).cloned())
+        .ok_or_else(|| format!("No rune etching with id {}", id))?;
+    if rune_etching.owner != owner {
+        return Err("Only the etcher can reveal a rune etching".to_string());
+    }
+    if let Some(reveal_txid) = rune_etching.reveal_txid {
+        return Err(format!("Rune etching was revealed in {}", reveal_txid));
+    }
+    if let Some(recovery_txid) = rune_etching.recovery_txid {
+        return Err(format!("Commit output was recovered in {}", recovery_txid));
+    }
+
+    let runestone = Runestone {
+        etching: Some(rune_etching.etching.clone()),
+        ..Default::default()
+    }
+    .encipher()?;
+    let commit =
+        ordinals::commit_address(&ctx, owner, &[rune_etching.etching.commitment()?]).await?;
+    let commit_txid = Txid::from_str(&rune_etching.commit_txid).unwrap();
+
+    // Find the commit output among the UTXOs of the commit address.
+    // Note that pagination may have to be used to get all UTXOs for the given address.
+    // For the sake of simplicity, it is assumed here that the `utxo` field in the response
+    // contains all UTXOs.
+    let utxos_response = bitcoin_get_utxos(&GetUtxosRequest {
+        address: commit.address.to_string(),
+        network: ctx.network,
+        filter: None,
+    })
+    .await
+    .map_err(|e| format!("Failed to get UTXOs: {:?}", e))?;
+    let commit_utxo = utxos_response
+        .utxos
+        .iter()
+        .find(|utxo| outpoint(utxo).txid == commit_txid)
+        .ok_or_else(|| format!("Commit transaction {} is not confirmed yet", commit_txid))?;
+
+    let confirmations = utxos_response.tip_height + 1 - commit_utxo.height;
+    if confirmations < COMMIT_CONFIRMATIONS {
+        return Err(format!(
+            "Commit transaction has {} of {} confirmations",
+            confirmations, COMMIT_CONFIRMATIONS
+        ));
+    }
+
+    let account = Account::derive(&ctx, owner, DEFAULT_ACCOUNT, AddressType::P2tr).await?;
+
+    // The etching transaction pays the fee the commit output was funded with, whatever the
+    // fee rate is by now, so that the premine lands on an output of exactly the postage.
+    let reveal_fee = rune_etching.commit_value - dust_limit(&account.address);
+    let (transaction, prevouts) = ordinals::build_reveal_transaction_with_fee(
+        &commit,
+        commit_txid,
+        commit_utxo.value,
+        &[PrimaryOutput::OpReturn(runestone)],
+        &account.address,
+        reveal_fee,
+    )?;
+    let signed_transaction =
+        ordinals::sign_reveal_transaction(&ctx, &commit, transaction, prevouts.as_slice()).await;
+
+    // Send the transaction to the Bitcoin network.
+    bitcoin_send_transaction(&SendTransactionRequest {
+        network: ctx.network,
+        transaction: serialize(&signed_transaction),
+    })
+    .await
+    .map_err(|e| format!("Failed to send transaction: {:?}", e))?;
+
+    // The premine goes to the first output after the runestone.
+    let reveal_txid = signed_transaction.compute_txid();
+    if rune_etching.etching.premine.unwrap_or_default() > 0 {
+        protect_output(owner, OutPoint::new(reveal_txid, 1));
+    }
+
+    let reveal_txid = reveal_txid.to_string();
+    mutate_state(|state| {
+        if let Some(rune_etching) = state.rune_etchings.get_mut(&id) {
+            rune_etching.reveal_txid = Some(reveal_txid.clone());
+        }
+    });
+
+    // Return the transaction ID.
+    Ok(reveal_txid)
+}
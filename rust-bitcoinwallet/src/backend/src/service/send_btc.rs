@@ -1,17 +1,22 @@
-use bitcoin::{consensus::serialize, key::Secp256k1, Address, PublicKey, XOnlyPublicKey};
+use bitcoin::{consensus::serialize, Address, OutPoint, Transaction, TxOut};
+use candid::Principal;
 use ic_cdk::{
     bitcoin_canister::{
-        bitcoin_get_utxos, bitcoin_send_transaction, GetUtxosRequest, SendTransactionRequest,
+        bitcoin_send_transaction, MillisatoshiPerByte, SendTransactionRequest, Utxo,
     },
     update,
 };
 use std::str::FromStr;
 
 use crate::{
+    account::{Account, AccountId, AddressType, DEFAULT_ACCOUNT},
     auth_guard,
-    btc::{get_fee_per_byte, PrimaryOutput},
-    p2tr::{self},
-    schnorr::{get_schnorr_public_key, sign_with_schnorr},
+    btc::{get_fee_per_byte_for, outpoint, BitcoinContext, FeePriority, PrimaryOutput},
+    coin_control::is_frozen,
+    coin_selection::CoinSelectionStrategy,
+    history::record_sent_transaction,
+    protection::is_protected,
+    state::read_state,
     BTC_CONTEXT,
 };
 
@@ -22,21 +27,165 @@ pub struct SendBtcRequest {
     pub amount_in_satoshi: u64,
 }
 
-/// Sends Bitcoin from the caller's Taproot address to the specified destination.
+/// Optional settings of a single send, overriding those of the account.
+#[derive(candid::CandidType, candid::Deserialize, Default)]
+pub struct SendOptions {
+    /// The coin selection strategy, instead of the account's default set with
+    /// `set_coin_selection`.
+    pub coin_selection: Option<CoinSelectionStrategy>,
+    /// The outputs to spend, as `txid:vout`, instead of letting coin selection choose. Each
+    /// must be an unspent output of the account that is neither protected nor frozen. All of
+    /// them are spent, in the given order, and nothing else.
+    pub inputs: Option<Vec<String>>,
+    /// The fee rate in satoshi per virtual byte, within the limits set by the controllers.
+    pub fee_rate: Option<u64>,
+    /// The priority the fee rate is estimated for, if `fee_rate` is not given. Defaults to
+    /// `normal`.
+    pub priority: Option<FeePriority>,
+}
+
+impl SendOptions {
+    /// Applies the options to the account about to send.
+    pub fn apply(&self, account: &mut Account) {
+        if let Some(coin_selection) = self.coin_selection {
+            account.coin_selection = coin_selection;
+        }
+    }
+
+    /// The fee rate of the send in millisatoshis per byte, either the one given explicitly or
+    /// estimated for the given priority.
+    pub async fn fee_per_byte(&self, ctx: &BitcoinContext) -> Result<MillisatoshiPerByte, String> {
+        match (self.fee_rate, self.priority) {
+            (Some(_), Some(_)) => Err("Give either a fee rate or a priority, not both".to_string()),
+            (Some(fee_rate), None) => read_state(|state| state.fee_rate_limits).check(fee_rate),
+            (None, priority) => Ok(get_fee_per_byte_for(ctx, priority.unwrap_or_default()).await),
+        }
+    }
+
+    /// Builds the transaction paying `primary_outputs` from `account`, spending the inputs
+    /// given in the options or those that coin selection picks among `own_utxos`.
+    pub async fn build_transaction(
+        &self,
+        ctx: &BitcoinContext,
+        account: &Account,
+        own_utxos: &[Utxo],
+        primary_outputs: &[PrimaryOutput],
+        fee_per_byte: MillisatoshiPerByte,
+    ) -> Result<(Transaction, Vec<TxOut>), String> {
+        match &self.inputs {
+            Some(inputs) => {
+                let inputs = chosen_inputs(account.principal, own_utxos, inputs)?;
+                account
+                    .build_transaction_from(ctx, &inputs, primary_outputs, fee_per_byte)
+                    .await
+            }
+            None => {
+                account
+                    .build_transaction(ctx, own_utxos, primary_outputs, fee_per_byte)
+                    .await
+            }
+        }
+    }
+}
+
+/// Looks up the outputs listed in `inputs` among the spendable UTXOs of `owner`.
+fn chosen_inputs(
+    owner: Principal,
+    own_utxos: &[Utxo],
+    inputs: &[String],
+) -> Result<Vec<Utxo>, String> {
+    if inputs.is_empty() {
+        return Err("At least one input is required".to_string());
+    }
+    let mut chosen: Vec<Utxo> = vec![];
+    for input in inputs {
+        let input = OutPoint::from_str(input).map_err(|e| format!("Invalid outpoint: {:?}", e))?;
+        if chosen.iter().any(|utxo| outpoint(utxo) == input) {
+            return Err(format!("Input {} is listed more than once", input));
+        }
+        if is_protected(owner, &input) {
+            return Err(format!(
+                "Input {} is protected; unprotect it or send it with send_utxo",
+                input
+            ));
+        }
+        if is_frozen(owner, &input) {
+            return Err(format!("Input {} is frozen; unfreeze it first", input));
+        }
+        let utxo = own_utxos
+            .iter()
+            .find(|utxo| outpoint(utxo) == input)
+            .ok_or_else(|| format!("Input {} is not an unspent output of the account", input))?;
+        chosen.push(utxo.clone());
+    }
+    Ok(chosen)
+}
+
+/// Sends Bitcoin from the caller's account to the specified destination.
+///
+/// The account and address type select which of the caller's addresses is spent from. They
+/// default to the default account and P2TR. P2TR accounts are spent using key path spending
+/// only — that is, a single Schnorr signature derived from the internal key with no script
+/// path committed. P2WPKH and P2PKH accounts are spent with a threshold ECDSA signature per
+/// input.
+///
+/// Outputs held by any of the account's receive addresses are spent alongside those of its
+/// main address, each signed with the key of the address holding it. Change always goes to the
+/// main address. The UTXOs to spend are picked by the account's coin selection strategy, or
+/// the one given in `options`, unless `options` lists the inputs to spend.
 ///
-/// This function constructs and broadcasts a transaction that spends from the caller's
-/// unique Taproot output using key path spending only — that is, a single Schnorr
-/// signature derived from the internal key with no script path committed.
+/// Spending from the inheritance account always spends all of its outputs, which restarts
+/// the heir's relative timelock for the remaining funds.
 #[update]
 pub async fn send_btc(
     destination_address: String,
     amount_in_satoshi: u64,
+    address_type: Option<AddressType>,
+    account: Option<AccountId>,
+    options: Option<SendOptions>,
 ) -> Result<String, String> {
     // Calls to send_btc need to be authenticated
     auth_guard()?;
 
     let ctx = BTC_CONTEXT.with(|ctx| ctx.get());
+    let (account, transaction, prevouts) = build_send(
+        &ctx,
+        destination_address,
+        amount_in_satoshi,
+        address_type,
+        account,
+        options.unwrap_or_default(),
+    )
+    .await?;
+
+    // Sign the transaction with the threshold keys matching the account's address type.
+    let signed_transaction = account
+        .sign_transaction(&ctx, transaction, prevouts.as_slice())
+        .await;
+
+    // Send the transaction to the Bitcoin network.
+    bitcoin_send_transaction(&SendTransactionRequest {
+        network: ctx.network,
+        transaction: serialize(&signed_transaction),
+    })
+    .await
+    .map_err(|e| format!("Failed to send transaction: {:?}", e))?;
+    record_sent_transaction(&account, &signed_transaction, &prevouts, 1);
+
+    // Return the transaction ID.
+    Ok(signed_transaction.compute_txid().to_string())
+}
 
+/// Builds the unsigned transaction of a `send_btc` call by the caller, returning it with the
+/// account it spends from and its previous outputs. `preview_send` stops here.
+pub(crate) async fn build_send(
+    ctx: &BitcoinContext,
+    destination_address: String,
+    amount_in_satoshi: u64,
+    address_type: Option<AddressType>,
+    account: Option<AccountId>,
+    options: SendOptions,
+) -> Result<(Account, Transaction, Vec<TxOut>), String> {
     if amount_in_satoshi == 0 {
         return Err("Amount must be greater than 0".to_string());
     }
@@ -50,72 +199,37 @@ pub async fn send_btc(
 
     let sender_principal = ic_cdk::api::msg_caller();
 
-    let internal_key_path = vec![sender_principal.as_slice().to_vec()];
-
-    // Derive the public key used as the internal key (untweaked key path base).
-    // This key is used for key path spending only, without any committed script tree.
-    let internal_key = get_schnorr_public_key(&ctx, internal_key_path.clone()).await;
-
-    // Convert the internal key to an x-only public key, as required by Taproot (BIP-341).
-    let internal_key = XOnlyPublicKey::from(PublicKey::from_slice(&internal_key).unwrap());
-
-    // Create a Taproot address using the internal key only.
-    // We pass `None` as the Merkle root, which per BIP-341 means the address commits
-    // to an unspendable script path, enabling only key path spending.
-    let secp256k1_engine = Secp256k1::new();
-    let own_address = Address::p2tr(&secp256k1_engine, internal_key, None, ctx.bitcoin_network);
+    // Derive the sender's account, which determines both the address to spend
+    // from and the signature scheme used for its inputs.
+    let mut account = Account::derive(
+        ctx,
+        sender_principal,
+        account.unwrap_or(DEFAULT_ACCOUNT),
+        address_type.unwrap_or_default(),
+    )
+    .await?;
+    options.apply(&mut account);
+    account.load_receive_addresses(ctx).await;
 
-    // Get all UTXOs for the sender's address.
-    // Note that pagination may have to be used to get all UTXOs for the given address.
-    // For the sake of simplicity, it is assumed here that the `utxo` field in the response
-    // contains all UTXOs.
-    let own_utxos = bitcoin_get_utxos(&GetUtxosRequest {
-        address: own_address.to_string(),
-        network: ctx.network,
-        filter: None,
-    })
-    .await
-    .map_err(|e| format!("Failed to get UTXOs: {:?}", e))?
-    .utxos;
+    // Get all UTXOs of the account's main and receive addresses, except protected and frozen
+    // ones.
+    let own_utxos = account.get_spendable_utxos(ctx).await?;
 
     if own_utxos.is_empty() {
         return Err("No UTXOs available for spending".to_string());
     }
 
     // Build the transaction
-    let fee_per_byte = get_fee_per_byte(&ctx).await;
-    let (transaction, prevouts) = p2tr::build_transaction(
-        &ctx,
-        &own_address,
-        &own_utxos,
-        p2tr::SelectUtxosMode::Greedy,
-        &PrimaryOutput::Address(dst_address, amount_in_satoshi),
-        fee_per_byte,
-    )
-    .await?;
-
-    // Sign the transaction using key path spending.
-    // For now, we use the mock signer since the actual Schnorr API integration
-    // would require proper key derivation setup.
-    let signed_transaction = p2tr::sign_transaction_key_spend(
-        &ctx,
-        &own_address,
-        transaction,
-        prevouts.as_slice(),
-        internal_key_path,
-        vec![], // No Merkle root for key-path-only spending
-        sign_with_schnorr,
-    )
-    .await;
-
-    // Send the transaction to the Bitcoin network.
-    bitcoin_send_transaction(&SendTransactionRequest {
-        network: ctx.network,
-        transaction: serialize(&signed_transaction),
-    })
-    .await
-    .map_err(|e| format!("Failed to send transaction: {:?}", e))?;
-
-    // Return the transaction ID.
-    Ok(signed_transaction.compute_txid().to_string())
+    let fee_per_byte = options.fee_per_byte(ctx).await?;
+    let (transaction, prevouts) = options
+        .build_transaction(
+            ctx,
+            &account,
+            &own_utxos,
+            &[PrimaryOutput::Address(dst_address, amount_in_satoshi)],
+            fee_per_byte,
+        )
+        .await?;
+
+    Ok((account, transaction, prevouts))
 }
@@ -0,0 +1,112 @@
+use bitcoin::{consensus::serialize, Address};
+use ic_cdk::{
+    bitcoin_canister::{bitcoin_send_transaction, SendTransactionRequest},
+    update,
+};
+use std::str::FromStr;
+
+use crate::{
+    account::{Account, AccountId, AddressType, DEFAULT_ACCOUNT},
+    auth_guard,
+    btc::PrimaryOutput,
+    history::record_sent_transaction,
+    notary::op_return_script,
+    service::send_btc::{SendBtcRequest, SendOptions},
+    BTC_CONTEXT,
+};
+
+/// Upper bound on the number of payments in a single transaction, which keeps it well below
+/// the standard transaction size.
+const MAX_PAYMENTS: usize = 1_000;
+
+/// Pays several recipients from the caller's account in a single transaction.
+///
+/// Each payment becomes an output, in the given order, optionally followed by an OP_RETURN
+/// output carrying `memo`, of at most 80 bytes. Change goes last. The fee is computed once
+/// for the combined transaction, which is cheaper than one transaction per recipient. The
+/// account, address type and options select the addresses and UTXOs to spend, as for
+/// `send_btc`.
+#[update]
+pub async fn send_many(
+    payments: Vec<SendBtcRequest>,
+    memo: Option<Vec<u8>>,
+    address_type: Option<AddressType>,
+    account: Option<AccountId>,
+    options: Option<SendOptions>,
+) -> Result<String, String> {
+    // Calls to send_many need to be authenticated
+    auth_guard()?;
+
+    let ctx = BTC_CONTEXT.with(|ctx| ctx.get());
+
+    if payments.is_empty() || payments.len() > MAX_PAYMENTS {
+        return Err(format!(
+            "Number of payments must be between 1 and {}",
+            MAX_PAYMENTS
+        ));
+    }
+
+    // Parse and validate the destination addresses. The address types need to be
+    // valid for the Bitcoin network we are on.
+    let mut primary_outputs = vec![];
+    for payment in payments {
+        if payment.amount_in_satoshi == 0 {
+            return Err("Amount must be greater than 0".to_string());
+        }
+        let dst_address = Address::from_str(&payment.destination_address)
+            .map_err(|e| format!("Invalid destination address: {}", e))?
+            .require_network(ctx.bitcoin_network)
+            .map_err(|e| format!("Address not valid for network: {}", e))?;
+        primary_outputs.push(PrimaryOutput::Address(
+            dst_address,
+            payment.amount_in_satoshi,
+        ));
+    }
+    if let Some(memo) = memo {
+        primary_outputs.push(PrimaryOutput::OpReturn(op_return_script(&memo)?));
+    }
+
+    let mut account = Account::derive(
+        &ctx,
+        ic_cdk::api::msg_caller(),
+        account.unwrap_or(DEFAULT_ACCOUNT),
+        address_type.unwrap_or_default(),
+    )
+    .await?;
+    let options = options.unwrap_or_default();
+    options.apply(&mut account);
+    account.load_receive_addresses(&ctx).await;
+
+    // Get all UTXOs of the account's main and receive addresses, except protected and frozen
+    // ones.
+    let own_utxos = account.get_spendable_utxos(&ctx).await?;
+
+    if own_utxos.is_empty() {
+        return Err("No UTXOs available for spending".to_string());
+    }
+
+    let fee_per_byte = options.fee_per_byte(&ctx).await?;
+    let (transaction, prevouts) = options
+        .build_transaction(&ctx, &account, &own_utxos, &primary_outputs, fee_per_byte)
+        .await?;
+    let signed_transaction = account
+        .sign_transaction(&ctx, transaction, prevouts.as_slice())
+        .await;
+
+    // Send the transaction to the Bitcoin network.
+    bitcoin_send_transaction(&SendTransactionRequest {
+        network: ctx.network,
+        transaction: serialize(&signed_transaction),
+    })
+    .await
+    .map_err(|e| format!("Failed to send transaction: {:?}", e))?;
+    record_sent_transaction(
+        &account,
+        &signed_transaction,
+        &prevouts,
+        primary_outputs.len(),
+    );
+
+    // Return the transaction ID.
+    Ok(signed_transaction.compute_txid().to_string())
+}
@@ -0,0 +1,97 @@
+use bitcoin::{consensus::serialize, Address, OutPoint};
+use ic_cdk::{
+    bitcoin_canister::{bitcoin_send_transaction, SendTransactionRequest},
+    update,
+};
+use std::str::FromStr;
+
+use crate::{
+    account::{Account, AddressType, DEFAULT_ACCOUNT},
+    auth_guard,
+    btc::{get_fee_per_byte, outpoint, PrimaryOutput},
+    coin_control::{is_frozen, is_spendable},
+    protection::unprotect_output,
+    runes::remove_rune_outputs,
+    BTC_CONTEXT,
+};
+
+/// Sends one of the caller's outputs whole to the destination address, e.g. to transfer an
+/// inscription.
+///
+/// The output, given as `txid:vout`, must belong to the caller's default P2TR account, which
+/// is where inscriptions and runes created through the smart contract end up. It is spent by
+/// the first input and pays the first output, which has the same value, so that every sat of
+/// it, and any inscription on it, goes to the destination. The output may be protected, but not
+/// frozen. The fee is paid from other outputs of the account that are neither, added as later
+/// inputs, and any change comes last. Runes the output carries move along with it.
+#[update]
+pub async fn send_utxo(
+    outpoint_to_send: String,
+    destination_address: String,
+) -> Result<String, String> {
+    // Calls to send_utxo need to be authenticated
+    auth_guard()?;
+
+    let ctx = BTC_CONTEXT.with(|ctx| ctx.get());
+    let owner = ic_cdk::api::msg_caller();
+
+    let outpoint_to_send =
+        OutPoint::from_str(&outpoint_to_send).map_err(|e| format!("Invalid outpoint: {:?}", e))?;
+    if is_frozen(owner, &outpoint_to_send) {
+        return Err(format!(
+            "Output {} is frozen; unfreeze it first",
+            outpoint_to_send
+        ));
+    }
+    let destination_address = Address::from_str(&destination_address)
+        .map_err(|e| format!("Invalid destination address: {}", e))?
+        .require_network(ctx.bitcoin_network)
+        .map_err(|e| format!("Address not valid for network: {}", e))?;
+
+    let mut account = Account::derive(&ctx, owner, DEFAULT_ACCOUNT, AddressType::P2tr).await?;
+    account.load_receive_addresses(&ctx).await;
+
+    // Get all UTXOs of the account's main and receive addresses, and set apart the one to
+    // send. The fee is paid from the others that are neither protected nor frozen.
+    let (utxos_to_send, own_utxos): (Vec<_>, Vec<_>) = account
+        .get_utxos(&ctx)
+        .await?
+        .into_iter()
+        .partition(|utxo| outpoint(utxo) == outpoint_to_send);
+    let value = utxos_to_send
+        .first()
+        .map(|utxo| utxo.value)
+        .ok_or_else(|| format!("No confirmed output {} in the account", outpoint_to_send))?;
+    let own_utxos: Vec<_> = own_utxos
+        .into_iter()
+        .filter(|utxo| is_spendable(owner, &outpoint(utxo)))
+        .collect();
+
+    let fee_per_byte = get_fee_per_byte(&ctx).await;
+    let (transaction, prevouts) = account
+        .build_transaction_spending(
+            &ctx,
+            &utxos_to_send,
+            &own_utxos,
+            &[PrimaryOutput::Address(destination_address, value)],
+            fee_per_byte,
+        )
+        .await?;
+    let signed_transaction = account
+        .sign_transaction(&ctx, transaction, prevouts.as_slice())
+        .await;
+
+    // Send the transaction to the Bitcoin network.
+    bitcoin_send_transaction(&SendTransactionRequest {
+        network: ctx.network,
+        transaction: serialize(&signed_transaction),
+    })
+    .await
+    .map_err(|e| format!("Failed to send transaction: {:?}", e))?;
+
+    unprotect_output(owner, outpoint_to_send);
+    remove_rune_outputs(owner, &[outpoint_to_send]);
+
+    // Return the transaction ID.
+    Ok(signed_transaction.compute_txid().to_string())
+}
@@ -0,0 +1,27 @@
+use ic_cdk::update;
+
+use crate::{
+    account::{ensure_account_exists, AccountId, DEFAULT_ACCOUNT},
+    auth_guard,
+    coin_selection::CoinSelectionStrategy,
+    state::mutate_state,
+};
+
+/// Sets the coin selection strategy the caller's account uses by default, for all of its
+/// address types. Individual sends can override it with their options.
+#[update]
+pub fn set_coin_selection(
+    strategy: CoinSelectionStrategy,
+    account: Option<AccountId>,
+) -> Result<(), String> {
+    // Calls to set_coin_selection need to be authenticated
+    auth_guard()?;
+
+    let caller = ic_cdk::api::msg_caller();
+    let account = account.unwrap_or(DEFAULT_ACCOUNT);
+    ensure_account_exists(caller, account)?;
+
+    mutate_state(|state| state.coin_selection.insert((caller, account), strategy));
+
+    Ok(())
+}
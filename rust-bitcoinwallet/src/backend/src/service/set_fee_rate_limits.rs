@@ -0,0 +1,18 @@
+use ic_cdk::update;
+
+use crate::{btc::FeeRateLimits, state::mutate_state};
+
+/// Sets the bounds on the fee rate of every transaction, in satoshi per virtual byte. Only
+/// controllers of the smart contract may change them.
+#[update]
+pub fn set_fee_rate_limits(limits: FeeRateLimits) -> Result<(), String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::api::msg_caller()) {
+        return Err("Only controllers can set the fee rate limits".to_string());
+    }
+    if limits.min_sat_per_vbyte == 0 || limits.min_sat_per_vbyte > limits.max_sat_per_vbyte {
+        return Err("Fee rate limits must satisfy 0 < min <= max".to_string());
+    }
+
+    mutate_state(|state| state.fee_rate_limits = limits);
+    Ok(())
+}
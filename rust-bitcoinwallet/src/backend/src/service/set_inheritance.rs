@@ -0,0 +1,66 @@
+use candid::Principal;
+use ic_cdk::{
+    bitcoin_canister::{bitcoin_get_balance, GetBalanceRequest},
+    update,
+};
+
+use crate::{
+    account::{Account, AddressType, DEFAULT_ACCOUNT},
+    auth_guard,
+    policy::InheritancePlan,
+    state::{mutate_state, read_state},
+    BTC_CONTEXT,
+};
+
+/// Sets up the caller's inheritance plan and returns the address of the inheritance account.
+///
+/// The heir can claim funds from the inheritance account once they have not moved for
+/// `inactivity_blocks` blocks. Since the address commits to the plan, a plan can only be
+/// replaced while the current inheritance account is empty.
+#[update]
+pub async fn set_inheritance(heir: Principal, inactivity_blocks: u16) -> Result<String, String> {
+    // Calls to set_inheritance need to be authenticated
+    auth_guard()?;
+
+    let owner = ic_cdk::api::msg_caller();
+
+    if heir == owner || heir == Principal::anonymous() {
+        return Err("The heir must be another, non-anonymous principal".to_string());
+    }
+    if inactivity_blocks == 0 {
+        return Err("Inactivity period must be at least one block".to_string());
+    }
+
+    let ctx = BTC_CONTEXT.with(|ctx| ctx.get());
+
+    if read_state(|state| state.inheritance_plans.contains_key(&owner)) {
+        let current =
+            Account::derive(&ctx, owner, DEFAULT_ACCOUNT, AddressType::Inheritance).await?;
+        let balance = bitcoin_get_balance(&GetBalanceRequest {
+            address: current.address.to_string(),
+            network: ctx.network,
+            min_confirmations: Some(0),
+        })
+        .await
+        .map_err(|e| format!("Failed to get balance: {:?}", e))?;
+        if balance > 0 {
+            return Err(format!(
+                "Inheritance account {} still holds {} satoshi",
+                current.address, balance
+            ));
+        }
+    }
+
+    mutate_state(|state| {
+        state.inheritance_plans.insert(
+            owner,
+            InheritancePlan {
+                heir,
+                inactivity_blocks,
+            },
+        )
+    });
+
+    let account = Account::derive(&ctx, owner, DEFAULT_ACCOUNT, AddressType::Inheritance).await?;
+    Ok(account.address.to_string())
+}
@@ -0,0 +1,172 @@
+use bitcoin::{consensus::serialize, Address};
+use ic_cdk::{
+    bitcoin_canister::{
+        bitcoin_get_utxos, bitcoin_send_transaction, GetUtxosRequest, SendTransactionRequest,
+    },
+    update,
+};
+use std::str::FromStr;
+
+use crate::{
+    auth_guard,
+    btc::{get_fee_per_byte, PrimaryOutput},
+    coin_selection::CoinSelectionStrategy,
+    p2tr,
+    schnorr::sign_with_schnorr,
+    state::read_state,
+    BTC_CONTEXT,
+};
+
+/// How a policy account is spent.
+#[derive(candid::CandidType, candid::Deserialize)]
+pub enum SpendPath {
+    /// A single signature of the key path owner, tweaked with the script tree.
+    #[serde(rename = "key_path")]
+    KeyPath,
+    /// The leaf at index `leaf`, satisfied with the caller's signature and the given
+    /// hash preimages.
+    #[serde(rename = "script_path")]
+    ScriptPath { leaf: u32, preimages: Vec<Vec<u8>> },
+}
+
+/// Sends Bitcoin from a policy account to the specified destination.
+///
+/// The smart contract only ever signs with the caller's key: a key path spend requires the
+/// caller to be the account's key path owner, and a script path spend requires the chosen
+/// leaf to be satisfiable with the caller's signature and the given preimages. Outputs that
+/// have not yet met the leaf's relative timelock are not spent.
+#[update]
+pub async fn spend_policy_account(
+    id: u64,
+    destination_address: String,
+    amount_in_satoshi: u64,
+    path: SpendPath,
+) -> Result<String, String> {
+    // Calls to spend_policy_account need to be authenticated
+    auth_guard()?;
+
+    let ctx = BTC_CONTEXT.with(|ctx| ctx.get());
+
+    if amount_in_satoshi == 0 {
+        return Err("Amount must be greater than 0".to_string());
+    }
+
+    // Parse and validate the destination address. The address type needs to be
+    // valid for the Bitcoin network we are on.
+    let dst_address = Address::from_str(&destination_address)
+        .map_err(|e| format!("Invalid destination address: {}", e))?
+        .require_network(ctx.bitcoin_network)
+        .map_err(|e| format!("Address not valid for network: {}", e))?;
+
+    let caller = ic_cdk::api::msg_caller();
+
+    let account = read_state(|state| state.policy_accounts.get(&id).cloned())
+        .ok_or_else(|| format!("No policy account with id {}", id))?;
+    let compiled = account.compile(&ctx).await?;
+    let own_address = &compiled.address;
+
+    // Get all UTXOs for the account's address.
+    // Note that pagination may have to be used to get all UTXOs for the given address.
+    // For the sake of simplicity, it is assumed here that the `utxo` field in the response
+    // contains all UTXOs.
+    let utxos_response = bitcoin_get_utxos(&GetUtxosRequest {
+        address: own_address.to_string(),
+        network: ctx.network,
+        filter: None,
+    })
+    .await
+    .map_err(|e| format!("Failed to get UTXOs: {:?}", e))?;
+
+    let fee_per_byte = get_fee_per_byte(&ctx).await;
+    let primary_outputs = [PrimaryOutput::Address(dst_address, amount_in_satoshi)];
+
+    let signed_transaction = match path {
+        SpendPath::KeyPath => {
+            if account.key_path_owner != Some(caller) {
+                return Err("Only the key path owner can spend through the key path".to_string());
+            }
+
+            let own_utxos = utxos_response.utxos;
+            if own_utxos.is_empty() {
+                return Err("No UTXOs available for spending".to_string());
+            }
+
+            let (transaction, prevouts) = p2tr::build_transaction(
+                &ctx,
+                own_address,
+                &own_utxos,
+                &CoinSelectionStrategy::default(),
+                &primary_outputs,
+                fee_per_byte,
+            )
+            .await?;
+
+            // The internal key is tweaked with the Merkle root of the script tree.
+            p2tr::sign_transaction_key_spend(
+                &ctx,
+                transaction,
+                prevouts.as_slice(),
+                &vec![vec![caller.as_slice().to_vec()]; prevouts.len()],
+                compiled.merkle_root_hash(),
+                sign_with_schnorr,
+            )
+            .await
+        }
+        SpendPath::ScriptPath { leaf, preimages } => {
+            let script_spend = compiled.script_spend(leaf, &[caller], &preimages)?;
+            let leaf = compiled.leaf(leaf)?;
+
+            // The transaction can at the earliest be mined in the block after the tip.
+            let next_height = utxos_response.tip_height + 1;
+            if leaf.absolute_timelock() >= next_height {
+                return Err(format!(
+                    "Leaf is locked until block {}, current height is {}",
+                    leaf.absolute_timelock(),
+                    utxos_response.tip_height
+                ));
+            }
+
+            // Only outputs that have been confirmed for long enough satisfy the relative timelock.
+            let own_utxos: Vec<_> = utxos_response
+                .utxos
+                .into_iter()
+                .filter(|utxo| next_height - utxo.height >= leaf.relative_timelock() as u32)
+                .collect();
+            if own_utxos.is_empty() {
+                return Err("No UTXOs available for spending through this leaf".to_string());
+            }
+
+            let (transaction, prevouts) = p2tr::build_transaction_script_spend(
+                &ctx,
+                own_address,
+                &own_utxos,
+                &CoinSelectionStrategy::default(),
+                &primary_outputs,
+                fee_per_byte,
+                &script_spend,
+            )
+            .await?;
+
+            p2tr::sign_transaction_script_spend(
+                &ctx,
+                own_address,
+                transaction,
+                prevouts.as_slice(),
+                &script_spend,
+                sign_with_schnorr,
+            )
+            .await
+        }
+    };
+
+    // Send the transaction to the Bitcoin network.
+    bitcoin_send_transaction(&SendTransactionRequest {
+        network: ctx.network,
+        transaction: serialize(&signed_transaction),
+    })
+    .await
+    .map_err(|e| format!("Failed to send transaction: {:?}", e))?;
+
+    // Return the transaction ID.
+    Ok(signed_transaction.compute_txid().to_string())
+}
@@ -0,0 +1,76 @@
+use bitcoin::{consensus::serialize, Address};
+use ic_cdk::{
+    bitcoin_canister::{bitcoin_send_transaction, SendTransactionRequest},
+    update,
+};
+use std::str::FromStr;
+
+use crate::{
+    account::{Account, AccountId, AddressType, DEFAULT_ACCOUNT},
+    auth_guard,
+    btc::get_fee_per_byte,
+    history::record_sent_transaction,
+    BTC_CONTEXT,
+};
+
+/// Sends the entire spendable balance of the caller's account to the destination address.
+///
+/// Every UTXO of the account's main and receive addresses is spent, except protected and
+/// frozen ones, and the destination receives their total value minus the exact fee, without
+/// a change output. This empties the account without leaving dust behind. The account and
+/// address type select the addresses to spend from, as for `send_btc`.
+#[update]
+pub async fn sweep(
+    destination_address: String,
+    address_type: Option<AddressType>,
+    account: Option<AccountId>,
+) -> Result<String, String> {
+    // Calls to sweep need to be authenticated
+    auth_guard()?;
+
+    let ctx = BTC_CONTEXT.with(|ctx| ctx.get());
+
+    // Parse and validate the destination address. The address type needs to be
+    // valid for the Bitcoin network we are on.
+    let dst_address = Address::from_str(&destination_address)
+        .map_err(|e| format!("Invalid destination address: {}", e))?
+        .require_network(ctx.bitcoin_network)
+        .map_err(|e| format!("Address not valid for network: {}", e))?;
+
+    let mut account = Account::derive(
+        &ctx,
+        ic_cdk::api::msg_caller(),
+        account.unwrap_or(DEFAULT_ACCOUNT),
+        address_type.unwrap_or_default(),
+    )
+    .await?;
+    account.load_receive_addresses(&ctx).await;
+
+    // Get all UTXOs of the account's main and receive addresses, except protected and frozen
+    // ones.
+    let own_utxos = account.get_spendable_utxos(&ctx).await?;
+
+    if own_utxos.is_empty() {
+        return Err("No UTXOs available for spending".to_string());
+    }
+
+    let fee_per_byte = get_fee_per_byte(&ctx).await;
+    let (transaction, prevouts) = account
+        .build_sweep_transaction(&ctx, &own_utxos, &dst_address, fee_per_byte)
+        .await?;
+    let signed_transaction = account
+        .sign_transaction(&ctx, transaction, prevouts.as_slice())
+        .await;
+
+    // Send the transaction to the Bitcoin network.
+    bitcoin_send_transaction(&SendTransactionRequest {
+        network: ctx.network,
+        transaction: serialize(&signed_transaction),
+    })
+    .await
+    .map_err(|e| format!("Failed to send transaction: {:?}", e))?;
+    record_sent_transaction(&account, &signed_transaction, &prevouts, 0);
+
+    // Return the transaction ID.
+    Ok(signed_transaction.compute_txid().to_string())
+}
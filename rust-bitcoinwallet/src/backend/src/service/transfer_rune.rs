@@ -0,0 +1,141 @@
+use bitcoin::{consensus::serialize, Address, OutPoint};
+use ic_cdk::{
+    bitcoin_canister::{bitcoin_send_transaction, SendTransactionRequest},
+    update,
+};
+use std::str::FromStr;
+
+use crate::{
+    account::{Account, AddressType, DEFAULT_ACCOUNT},
+    auth_guard,
+    btc::{dust_limit, get_fee_per_byte, outpoint, PrimaryOutput},
+    coin_control::{is_frozen, is_spendable},
+    protection::unprotect_output,
+    runes::{
+        partition_rune_utxos, record_rune_output, remove_rune_outputs, Edict, RuneBalances, RuneId,
+        Runestone,
+    },
+    BTC_CONTEXT,
+};
+
+/// Transfers `amount` runes of the rune `rune_id` from the caller's default P2TR account to
+/// the destination address.
+///
+/// The inputs are chosen among the caller's confirmed outputs that the smart contract knows
+/// to carry the rune, see `get_rune_balances`, except frozen ones, and the fee is paid from
+/// outputs without runes. The transaction carries a runestone with an edict moving the amount
+/// to a dust-sized output to the destination. Any runes of the inputs that are not
+/// transferred, including other runes, go to a dust-sized change output to the caller's P2TR
+/// address, instead of being burned.
+#[update]
+pub async fn transfer_rune(
+    rune_id: RuneId,
+    amount: u128,
+    destination_address: String,
+) -> Result<String, String> {
+    // Calls to transfer_rune need to be authenticated
+    auth_guard()?;
+
+    if amount == 0 {
+        return Err("Amount must be positive".to_string());
+    }
+
+    let ctx = BTC_CONTEXT.with(|ctx| ctx.get());
+    let owner = ic_cdk::api::msg_caller();
+
+    let destination_address = Address::from_str(&destination_address)
+        .map_err(|e| format!("Invalid destination address: {}", e))?
+        .require_network(ctx.bitcoin_network)
+        .map_err(|e| format!("Address not valid for network: {}", e))?;
+
+    let mut account = Account::derive(&ctx, owner, DEFAULT_ACCOUNT, AddressType::P2tr).await?;
+    account.load_receive_addresses(&ctx).await;
+
+    // Get all UTXOs of the account's main and receive addresses, split by whether they carry
+    // runes. The fee is paid from those that are neither protected nor frozen.
+    let (rune_utxos, own_utxos) = partition_rune_utxos(owner, account.get_utxos(&ctx).await?);
+    let own_utxos: Vec<_> = own_utxos
+        .into_iter()
+        .filter(|utxo| is_spendable(owner, &outpoint(utxo)))
+        .collect();
+
+    // Pick outputs carrying the rune until they cover the amount, and sum up all runes they
+    // carry. Frozen outputs are left alone, whatever runes they carry.
+    let mut inputs = vec![];
+    let mut remainder = RuneBalances::new();
+    for (utxo, balances) in rune_utxos {
+        if is_frozen(owner, &outpoint(&utxo)) {
+            continue;
+        }
+        if remainder.get(&rune_id).copied().unwrap_or_default() >= amount {
+            break;
+        }
+        if !balances.contains_key(&rune_id) {
+            continue;
+        }
+        for (id, balance) in balances {
+            *remainder.entry(id).or_default() += balance;
+        }
+        inputs.push(utxo);
+    }
+    let available = remainder.get(&rune_id).copied().unwrap_or_default();
+    if available < amount {
+        return Err(format!(
+            "Insufficient rune balance: {} in confirmed outputs that are not frozen, trying to \
+             transfer {}",
+            available, amount
+        ));
+    }
+    remainder.insert(rune_id, available - amount);
+    remainder.retain(|_, balance| *balance > 0);
+
+    // The destination comes right after the runestone, followed by the rune change output if
+    // any runes remain.
+    let mut runestone = Runestone {
+        edicts: vec![Edict {
+            id: rune_id,
+            amount,
+            output: 1,
+        }],
+        ..Default::default()
+    };
+    let mut primary_outputs = vec![PrimaryOutput::Address(
+        destination_address.clone(),
+        dust_limit(&destination_address),
+    )];
+    if !remainder.is_empty() {
+        runestone.pointer = Some(2);
+        primary_outputs.push(PrimaryOutput::Address(
+            account.address.clone(),
+            dust_limit(&account.address),
+        ));
+    }
+    primary_outputs.insert(0, PrimaryOutput::OpReturn(runestone.encipher()?));
+
+    let fee_per_byte = get_fee_per_byte(&ctx).await;
+    let (transaction, prevouts) = account
+        .build_transaction_spending(&ctx, &inputs, &own_utxos, &primary_outputs, fee_per_byte)
+        .await?;
+    let signed_transaction = account
+        .sign_transaction(&ctx, transaction, prevouts.as_slice())
+        .await;
+
+    // Send the transaction to the Bitcoin network.
+    bitcoin_send_transaction(&SendTransactionRequest {
+        network: ctx.network,
+        transaction: serialize(&signed_transaction),
+    })
+    .await
+    .map_err(|e| format!("Failed to send transaction: {:?}", e))?;
+
+    let txid = signed_transaction.compute_txid();
+    let spent: Vec<_> = inputs.iter().map(outpoint).collect();
+    remove_rune_outputs(owner, &spent);
+    for outpoint in spent {
+        unprotect_output(owner, outpoint);
+    }
+    record_rune_output(owner, OutPoint::new(txid, 2), remainder);
+
+    // Return the transaction ID.
+    Ok(txid.to_string())
+}
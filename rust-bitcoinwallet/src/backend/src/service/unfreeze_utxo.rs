@@ -0,0 +1,18 @@
+use bitcoin::OutPoint;
+use ic_cdk::update;
+use std::str::FromStr;
+
+use crate::{auth_guard, coin_control::unfreeze_output};
+
+/// Unfreezes one of the caller's outputs, given as `txid:vout`, so that it can fund payments
+/// and fees again.
+#[update]
+pub fn unfreeze_utxo(outpoint: String) -> Result<(), String> {
+    // Calls to unfreeze_utxo need to be authenticated
+    auth_guard()?;
+
+    let outpoint =
+        OutPoint::from_str(&outpoint).map_err(|e| format!("Invalid outpoint: {:?}", e))?;
+    unfreeze_output(ic_cdk::api::msg_caller(), outpoint);
+    Ok(())
+}
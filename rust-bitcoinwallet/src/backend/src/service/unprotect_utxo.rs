@@ -0,0 +1,20 @@
+use bitcoin::OutPoint;
+use ic_cdk::update;
+use std::str::FromStr;
+
+use crate::{auth_guard, protection::unprotect_output};
+
+/// Lifts the protection of one of the caller's outputs, given as `txid:vout`, so that it can
+/// fund payments and fees again.
+///
+/// Outputs the smart contract knows to carry runes stay protected.
+#[update]
+pub fn unprotect_utxo(outpoint: String) -> Result<(), String> {
+    // Calls to unprotect_utxo need to be authenticated
+    auth_guard()?;
+
+    let outpoint =
+        OutPoint::from_str(&outpoint).map_err(|e| format!("Invalid outpoint: {:?}", e))?;
+    unprotect_output(ic_cdk::api::msg_caller(), outpoint);
+    Ok(())
+}
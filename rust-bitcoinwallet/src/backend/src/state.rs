@@ -0,0 +1,108 @@
+// This module holds the smart contract state that must survive upgrades, such as accounts
+// defined by the users of the smart contract. Derived keys are not part of it, since they
+// can always be requested again from the management canister.
+//
+// The state lives on the heap and is written to stable memory before an upgrade and read
+// back afterwards.
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+};
+
+use candid::{CandidType, Deserialize, Principal};
+
+use crate::{
+    account::{AccountId, AddressType, ReceiveAddresses},
+    btc::FeeRateLimits,
+    coin_selection::CoinSelectionStrategy,
+    history::SentTransaction,
+    htlc::Htlc,
+    multisig::{SharedWallet, SpendProposal},
+    notary::Notarization,
+    ordinals::PendingReveal,
+    policy::{InheritancePlan, PolicyAccount},
+    runes::{RuneBalances, RuneEtching},
+};
+
+#[derive(CandidType, Deserialize, Default)]
+pub struct State {
+    /// Labels of the accounts of each principal, keyed by account id. The default account
+    /// only appears once it has been labeled.
+    pub accounts: BTreeMap<Principal, BTreeMap<AccountId, String>>,
+    /// Script-path capable P2TR accounts, keyed by their id.
+    pub policy_accounts: BTreeMap<u64, PolicyAccount>,
+    pub next_policy_account_id: u64,
+    /// Inheritance plans, keyed by the owner of the inheritance account.
+    pub inheritance_plans: BTreeMap<Principal, InheritancePlan>,
+    /// Wallets shared by several principals, keyed by their id.
+    pub shared_wallets: BTreeMap<u64, SharedWallet>,
+    pub next_shared_wallet_id: u64,
+    /// Spend proposals of shared wallets, keyed by their id.
+    pub spend_proposals: BTreeMap<u64, SpendProposal>,
+    pub next_spend_proposal_id: u64,
+    /// Receive addresses issued per account and address type, see
+    /// [`crate::account::Account::load_receive_addresses`].
+    pub receive_addresses: BTreeMap<(Principal, AccountId, AddressType), ReceiveAddresses>,
+    /// Hash time-locked contracts, keyed by their id.
+    pub htlcs: BTreeMap<u64, Htlc>,
+    pub next_htlc_id: u64,
+    /// Reveal transactions of inscriptions that have not been accepted yet, keyed by the txid
+    /// of their commit transaction.
+    pub pending_reveals: BTreeMap<String, PendingReveal>,
+    /// Transactions that notarized a piece of data, keyed by the data.
+    pub notarizations: BTreeMap<Vec<u8>, Vec<Notarization>>,
+    /// Rune etchings, keyed by their id.
+    pub rune_etchings: BTreeMap<u64, RuneEtching>,
+    pub next_rune_etching_id: u64,
+    /// Outputs known to carry runes, keyed by their owner and outpoint, see
+    /// [`crate::runes::record_rune_output`].
+    pub rune_outputs: BTreeMap<Principal, BTreeMap<String, RuneBalances>>,
+    /// Outpoints excluded from ordinary coin selection, keyed by their owner, see
+    /// [`crate::protection`].
+    pub protected_outputs: BTreeMap<Principal, BTreeSet<String>>,
+    /// Default coin selection strategy of each account that has one other than the default.
+    pub coin_selection: BTreeMap<(Principal, AccountId), CoinSelectionStrategy>,
+    /// Labels of outpoints, keyed by their owner, see [`crate::coin_control`].
+    pub utxo_labels: BTreeMap<Principal, BTreeMap<String, String>>,
+    /// Outpoints their owner has frozen, see [`crate::coin_control`].
+    pub frozen_outputs: BTreeMap<Principal, BTreeSet<String>>,
+    /// Bounds on the fee rate of every transaction.
+    pub fee_rate_limits: FeeRateLimits,
+    /// Payments sent from accounts, keyed by their txid, see [`crate::history`].
+    pub sent_transactions: BTreeMap<String, SentTransaction>,
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::default();
+}
+
+/// Runs `f` with read access to the smart contract state.
+pub fn read_state<R>(f: impl FnOnce(&State) -> R) -> R {
+    STATE.with_borrow(f)
+}
+
+/// Runs `f` with write access to the smart contract state.
+pub fn mutate_state<R>(f: impl FnOnce(&mut State) -> R) -> R {
+    STATE.with_borrow_mut(f)
+}
+
+/// Writes the state to stable memory. Called from the pre-upgrade hook.
+pub fn save() {
+    STATE
+        .with_borrow(|state| ic_cdk::storage::stable_save((state,)))
+        .expect("failed to save state to stable memory");
+}
+
+/// Restores the state from stable memory. Called from the post-upgrade hook.
+///
+/// Upgrading from a version that did not persist any state leaves stable memory empty,
+/// in which case the state starts out empty as well.
+pub fn restore() {
+    if ic_cdk::stable::stable_size() == 0 {
+        return;
+    }
+    let (state,): (State,) =
+        ic_cdk::storage::stable_restore().expect("failed to restore state from stable memory");
+    STATE.set(state);
+}
@@ -0,0 +1,52 @@
+// This module predicts the size of transactions before they are signed, from the lengths of
+// their scripts and witness elements. Signatures are assumed to take their maximal length,
+// so that a prediction never falls short of the size of the signed transaction, and the fee
+// of a transaction can be computed in a single pass.
+//
+// The transaction builders used to learn the size by signing the whole transaction with a mock
+// signer, over and over until the fee converged. Debug builds still sign every transaction
+// they build with a mock signer once, to cross-check the prediction, see `check_prediction`.
+
+use bitcoin::{
+    transaction::{predict_weight, InputWeightPrediction},
+    Transaction, TxOut,
+};
+use ic_cdk::bitcoin_canister::MillisatoshiPerByte;
+
+/// A P2TR input spent through the key path, with a 64-byte Schnorr signature.
+pub const P2TR_KEY_PATH: InputWeightPrediction = InputWeightPrediction::P2TR_KEY_DEFAULT_SIGHASH;
+
+/// A P2WPKH input, with a DER-encoded ECDSA signature of at most 72 bytes and a compressed key.
+pub const P2WPKH: InputWeightPrediction = InputWeightPrediction::P2WPKH_MAX;
+
+/// A P2PKH input, with a DER-encoded ECDSA signature of at most 72 bytes and a compressed key.
+pub const P2PKH: InputWeightPrediction = InputWeightPrediction::P2PKH_COMPRESSED_MAX;
+
+/// The virtual size of a transaction with `inputs` inputs like `input` and the given outputs.
+pub fn predict_vsize<'a>(
+    input: InputWeightPrediction,
+    inputs: usize,
+    outputs: impl IntoIterator<Item = &'a TxOut>,
+) -> u64 {
+    predict_weight(
+        std::iter::repeat_n(input, inputs),
+        outputs.into_iter().map(|output| output.script_pubkey.len()),
+    )
+    .to_vbytes_ceil()
+}
+
+/// The fee for `vsize` virtual bytes at `fee_per_byte`, rounded up.
+pub fn fee(vsize: u64, fee_per_byte: MillisatoshiPerByte) -> u64 {
+    (vsize * fee_per_byte).div_ceil(1000)
+}
+
+/// Checks in debug builds that `signed_transaction`, signed with a mock signer, is not larger
+/// than predicted.
+pub fn check_prediction(predicted_vsize: u64, signed_transaction: &Transaction) {
+    debug_assert!(
+        signed_transaction.vsize() as u64 <= predicted_vsize,
+        "predicted {} vB, but the signed transaction has {} vB",
+        predicted_vsize,
+        signed_transaction.vsize()
+    );
+}